@@ -0,0 +1,310 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+#[cfg(feature = "json")]
+use serde_json::json;
+
+use crate::statement::{Op, Statement};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// into the block right after
+    Fallthrough,
+    /// `jmp`, or a conditional jump when it is taken
+    Jump,
+    /// `run` into the routine
+    Call,
+    /// `ret` back to the statement after the `run`
+    Return,
+}
+
+impl EdgeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EdgeKind::Fallthrough => "fallthrough",
+            EdgeKind::Jump => "jump",
+            EdgeKind::Call => "call",
+            EdgeKind::Return => "return",
+        }
+    }
+}
+
+/// Statements that always run one after the other, only the first can be
+/// jumped to and only the last can jump.
+#[derive(Debug, Clone)]
+pub struct Block {
+    /// statements `start..end` of the program
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// Control-flow graph of a loaded program, edges go between indices into
+/// `blocks`.
+///
+/// Interrupt handlers start blocks but nothing leads to them, they can run
+/// between any two statements.
+#[derive(Debug, Clone, Default)]
+pub struct Cfg {
+    pub blocks: Vec<Block>,
+    pub edges: Vec<Edge>,
+}
+
+/// statement index of every label
+pub(crate) fn labels(program: &[Statement]) -> HashMap<&str, usize> {
+    program
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, statement)| Some((statement.label_name()?, idx)))
+        .collect()
+}
+
+/// statements that can run after `idx`, calls are followed into the
+/// routine when `calls` is set and skipped over otherwise
+pub(crate) fn successors(
+    program: &[Statement],
+    labels: &HashMap<&str, usize>,
+    idx: usize,
+    calls: bool,
+) -> Vec<usize> {
+    let statement = &program[idx];
+    let next = (idx + 1 < program.len()).then_some(idx + 1);
+    let target = statement
+        .target()
+        .and_then(|label| labels.get(label).copied());
+
+    match statement.op {
+        Op::JMP(_) => target.into_iter().collect(),
+        Op::JEQ(_) | Op::JNE(_) | Op::JLT(_) | Op::JGT(_) => {
+            target.into_iter().chain(next).collect()
+        }
+        Op::RUN(_) if calls => target.into_iter().collect(),
+        Op::RET(_) | Op::IRET(_) | Op::DIE(_) => Vec::new(),
+        _ => next.into_iter().collect(),
+    }
+}
+
+/// `ret` statements a routine starting at `entry` can return with
+fn returns(program: &[Statement], labels: &HashMap<&str, usize>, entry: usize) -> Vec<usize> {
+    let mut rets = Vec::new();
+    let mut stack = vec![entry];
+    let mut seen = HashSet::new();
+
+    while let Some(idx) = stack.pop() {
+        if !seen.insert(idx) {
+            continue;
+        }
+
+        if let Op::RET(_) = program[idx].op {
+            rets.push(idx);
+        }
+
+        stack.extend(successors(program, labels, idx, false));
+    }
+
+    rets
+}
+
+impl Cfg {
+    pub fn build(program: &[Statement]) -> Cfg {
+        let labels = labels(program);
+
+        let mut leaders = BTreeSet::new();
+        if !program.is_empty() {
+            leaders.insert(0);
+        }
+
+        for (idx, statement) in program.iter().enumerate() {
+            if statement.label.is_some() {
+                leaders.insert(idx);
+            }
+
+            let ends_block = matches!(
+                statement.op,
+                Op::JMP(_)
+                    | Op::JEQ(_)
+                    | Op::JNE(_)
+                    | Op::JLT(_)
+                    | Op::JGT(_)
+                    | Op::RUN(_)
+                    | Op::RET(_)
+                    | Op::IRET(_)
+                    | Op::DIE(_)
+            );
+            if ends_block && idx + 1 < program.len() {
+                leaders.insert(idx + 1);
+            }
+        }
+
+        let starts: Vec<usize> = leaders.into_iter().collect();
+        let blocks: Vec<Block> = starts
+            .iter()
+            .enumerate()
+            .map(|(id, &start)| Block {
+                start,
+                end: starts.get(id + 1).copied().unwrap_or(program.len()),
+            })
+            .collect();
+
+        let mut cfg = Cfg {
+            blocks,
+            edges: Vec::new(),
+        };
+
+        for (id, block) in cfg.blocks.iter().enumerate() {
+            let last = block.end - 1;
+            let statement = &program[last];
+            let target = statement
+                .target()
+                .and_then(|label| labels.get(label).copied());
+
+            for next in successors(program, &labels, last, true) {
+                let kind = match (&statement.op, Some(next) == target) {
+                    (Op::RUN(_), _) => EdgeKind::Call,
+                    (Op::IVT(_), _) | (_, false) => EdgeKind::Fallthrough,
+                    (_, true) => EdgeKind::Jump,
+                };
+
+                let edge = Edge {
+                    from: id,
+                    to: starts.binary_search(&next).unwrap(),
+                    kind,
+                };
+
+                if !cfg.edges.contains(&edge) {
+                    cfg.edges.push(edge);
+                }
+            }
+        }
+
+        for (idx, statement) in program.iter().enumerate() {
+            let (Op::RUN(_), Some(&target)) = (
+                &statement.op,
+                statement.target().and_then(|label| labels.get(label)),
+            ) else {
+                continue;
+            };
+
+            if idx + 1 == program.len() {
+                continue;
+            }
+
+            for ret in returns(program, &labels, target) {
+                let edge = Edge {
+                    from: cfg.block_of(ret).unwrap(),
+                    to: starts.binary_search(&(idx + 1)).unwrap(),
+                    kind: EdgeKind::Return,
+                };
+
+                if !cfg.edges.contains(&edge) {
+                    cfg.edges.push(edge);
+                }
+            }
+        }
+
+        cfg
+    }
+
+    /// block holding statement `idx`
+    pub fn block_of(&self, idx: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .position(|block| block.start <= idx && idx < block.end)
+    }
+
+    /// statements of a block as they are written, labels on their own line
+    fn lines(&self, program: &[Statement], block: &Block) -> Vec<String> {
+        let mut lines = Vec::new();
+        for statement in &program[block.start..block.end] {
+            if let Some(label) = statement.label_name() {
+                lines.push(format!("{label}:"));
+            }
+            if !matches!(statement.op, Op::NOP) {
+                lines.push(statement.text());
+            }
+        }
+        lines
+    }
+
+    /// Graphviz graph, blocks list their statements
+    pub fn to_dot(&self, program: &[Statement], name: &str) -> String {
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+
+        let mut dot = format!("digraph \"{}\" {{\n", escape(name));
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+
+        for (id, block) in self.blocks.iter().enumerate() {
+            let label: String = self
+                .lines(program, block)
+                .iter()
+                .map(|line| format!("{}\\l", escape(line)))
+                .collect();
+            dot.push_str(&format!("    b{id} [label=\"{label}\"];\n"));
+        }
+
+        for edge in &self.edges {
+            let attrs = match edge.kind {
+                EdgeKind::Fallthrough => String::new(),
+                EdgeKind::Jump => {
+                    let last = &program[self.blocks[edge.from].end - 1];
+                    format!(" [label=\"{}\"]", last.op.mnemonic())
+                }
+                EdgeKind::Call | EdgeKind::Return => {
+                    format!(" [label=\"{}\", style=dashed]", edge.kind.name())
+                }
+            };
+            dot.push_str(&format!("    b{} -> b{}{attrs};\n", edge.from, edge.to));
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// `{"blocks": [..], "edges": [..]}`, blocks with the statements they
+    /// cover and where they start
+    #[cfg(feature = "json")]
+    pub fn to_json(&self, program: &[Statement]) -> String {
+        let blocks: Vec<_> = self
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(id, block)| {
+                let first = &program[block.start];
+                let loc = match (&first.label, first.op.token()) {
+                    (Some(label), _) => Some(label.loc()),
+                    (None, Some(token)) => Some(token.loc()),
+                    (None, None) => None,
+                };
+
+                json!({
+                    "id": id,
+                    "start": block.start,
+                    "end": block.end,
+                    "label": first.label_name(),
+                    "location": loc.map(|loc| loc.to_string()),
+                    "statements": self.lines(program, block),
+                })
+            })
+            .collect();
+
+        let edges: Vec<_> = self
+            .edges
+            .iter()
+            .map(|edge| {
+                json!({
+                    "from": edge.from,
+                    "to": edge.to,
+                    "kind": edge.kind.name(),
+                })
+            })
+            .collect();
+
+        let graph = json!({ "blocks": blocks, "edges": edges });
+        serde_json::to_string_pretty(&graph).unwrap() + "\n"
+    }
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+
+use crate::Result;
+
+/// Storage type of registers and memory cells, values are masked to the
+/// configured word width.
+pub type Word = u32;
+
+/// largest addressable memory
+pub const MAX_MEM_SIZE: usize = 0x10000;
+
+/// decimal or `0x` prefixed hex address
+pub fn parse_address(addr: &str) -> Option<usize> {
+    match addr.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => addr.parse().ok(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordWidth {
+    Bits8,
+    Bits16,
+    Bits32,
+}
+
+impl WordWidth {
+    pub fn bits(&self) -> u32 {
+        match self {
+            WordWidth::Bits8 => 8,
+            WordWidth::Bits16 => 16,
+            WordWidth::Bits32 => 32,
+        }
+    }
+
+    pub fn from_bits(bits: u32) -> Option<WordWidth> {
+        match bits {
+            8 => Some(WordWidth::Bits8),
+            16 => Some(WordWidth::Bits16),
+            32 => Some(WordWidth::Bits32),
+            _ => None,
+        }
+    }
+
+    /// largest value a word can hold
+    pub fn mask(&self) -> Word {
+        match self {
+            WordWidth::Bits32 => Word::MAX,
+            _ => (1 << self.bits()) - 1,
+        }
+    }
+
+    /// `value` read as two's complement
+    pub fn signed(&self, value: Word) -> i64 {
+        let value = (value & self.mask()) as i64;
+        match value >> (self.bits() - 1) {
+            0 => value,
+            _ => value - (1 << self.bits()),
+        }
+    }
+
+    /// decimal, `0x` hex or `0b` binary, negative numbers are stored as two's
+    /// complement, `None` when it doesn't fit in a word
+    pub fn parse(&self, s: &str) -> Option<Word> {
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+
+        let value = match s.get(..2) {
+            Some("0x" | "0X") => u64::from_str_radix(&s[2..], 16),
+            Some("0b" | "0B") => u64::from_str_radix(&s[2..], 2),
+            _ => s.parse(),
+        }
+        .ok()?;
+
+        match negative {
+            true if value <= 1 << (self.bits() - 1) => {
+                Some((value as Word).wrapping_neg() & self.mask())
+            }
+            false if value <= self.mask() as u64 => Some(value as Word),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WordWidth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-bit", self.bits())
+    }
+}
+
+/// Shape of the machine a program runs on, see `DIS::new_with`.
+#[derive(Debug, Clone)]
+pub struct MachineConfig {
+    pub memory_size: usize,
+    /// general purpose registers, named `#0` to `#<registers - 1>`
+    pub registers: usize,
+    pub word_width: WordWidth,
+    /// chars are limited to ASCII instead of any Unicode scalar value
+    pub ascii: bool,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        MachineConfig {
+            memory_size: 4096,
+            registers: 8,
+            word_width: WordWidth::Bits16,
+            ascii: false,
+        }
+    }
+}
+
+impl MachineConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn memory_size(mut self, words: usize) -> Self {
+        self.memory_size = words;
+        self
+    }
+
+    pub fn registers(mut self, count: usize) -> Self {
+        self.registers = count;
+        self
+    }
+
+    pub fn word_width(mut self, width: WordWidth) -> Self {
+        self.word_width = width;
+        self
+    }
+
+    pub fn ascii(mut self, ascii: bool) -> Self {
+        self.ascii = ascii;
+        self
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.memory_size == 0 || self.memory_size > MAX_MEM_SIZE {
+            error!(
+                "memory size must be between 1 and {MAX_MEM_SIZE} words, got {}",
+                self.memory_size
+            );
+            return Err(());
+        }
+
+        // `rln` returns its count in #3
+        if self.registers < 4 || self.registers > 256 {
+            error!(
+                "register count must be between 4 and 256, got {}",
+                self.registers
+            );
+            return Err(());
+        }
+
+        Ok(())
+    }
+
+    /// names of all registers, `e` included
+    pub fn register_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = (0..self.registers).map(|i| i.to_string()).collect();
+        names.push("e".to_string());
+        names
+    }
+
+    pub fn has_register(&self, name: &str) -> bool {
+        name == "e"
+            || name
+                .parse::<usize>()
+                .is_ok_and(|idx| idx < self.registers && idx.to_string() == name)
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use crate::lexer::Location;
+use crate::statement::{Op, Statement};
+
+/// Statement hits and conditional jump directions, keyed by the location of
+/// the statement's keyword.
+///
+/// Lines read back from LCOV have no column information and are stored with
+/// column 0, reports add up every location on a line.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    pub statements: BTreeMap<Location, u64>,
+    /// `[taken, not taken]` per `jeq`, `jne`, `jlt` and `jgt`
+    pub branches: BTreeMap<Location, [u64; 2]>,
+}
+
+impl Coverage {
+    /// registers every statement of `program` as not executed yet
+    pub fn new(program: &[Statement]) -> Self {
+        let mut coverage = Self::default();
+
+        for statement in program {
+            if let Some(token) = statement.op.token() {
+                let loc = token.loc().clone();
+
+                if is_branch(&statement.op) {
+                    coverage.branches.insert(loc.clone(), [0, 0]);
+                }
+                coverage.statements.insert(loc, 0);
+            }
+        }
+
+        coverage
+    }
+
+    pub(crate) fn record_statement(&mut self, statement: &Statement) {
+        if let Some(token) = statement.op.token() {
+            *self.statements.entry(token.loc().clone()).or_insert(0) += 1;
+        }
+    }
+
+    pub(crate) fn record_branch(&mut self, statement: &Statement, taken: bool) {
+        if let Some(token) = statement.op.token() {
+            let counts = self.branches.entry(token.loc().clone()).or_insert([0, 0]);
+            counts[if taken { 0 } else { 1 }] += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Coverage) {
+        for (loc, hits) in &other.statements {
+            *self.statements.entry(loc.clone()).or_insert(0) += hits;
+        }
+
+        for (loc, [taken, not_taken]) in &other.branches {
+            let counts = self.branches.entry(loc.clone()).or_insert([0, 0]);
+            counts[0] += taken;
+            counts[1] += not_taken;
+        }
+    }
+
+    /// hits per line of every covered file
+    fn lines(&self) -> BTreeMap<&str, BTreeMap<usize, u64>> {
+        let mut files: BTreeMap<&str, BTreeMap<usize, u64>> = BTreeMap::new();
+
+        for (loc, hits) in &self.statements {
+            *files
+                .entry(loc.file())
+                .or_default()
+                .entry(loc.line())
+                .or_insert(0) += hits;
+        }
+
+        files
+    }
+
+    fn line_branches(&self, file: &str, line: usize) -> Vec<(&Location, &[u64; 2])> {
+        self.branches
+            .iter()
+            .filter(|(loc, _)| loc.file() == file && loc.line() == line)
+            .collect()
+    }
+
+    /// merges a tracefile written by `write_lcov`
+    pub fn read_lcov(&mut self, inp: &mut dyn BufRead) -> io::Result<()> {
+        let mut file = String::new();
+        let mut other = Coverage::default();
+
+        for line in inp.lines() {
+            let line = line?;
+            let (key, value) = line.split_once(':').unwrap_or((line.as_str(), ""));
+
+            let fields: Vec<u64> = value
+                .split(',')
+                .map(|field| field.trim().parse().unwrap_or(0))
+                .collect();
+
+            match key {
+                "SF" => file = value.to_string(),
+                "DA" if fields.len() >= 2 => {
+                    let loc = Location::new(file.clone(), fields[0] as usize, 0);
+                    *other.statements.entry(loc).or_insert(0) += fields[1];
+                }
+                "BRDA" if fields.len() >= 4 => {
+                    let loc = Location::new(file.clone(), fields[0] as usize, fields[1] as usize);
+                    let counts = other.branches.entry(loc).or_insert([0, 0]);
+                    counts[fields[2].min(1) as usize] += fields[3];
+                }
+                _ => {}
+            }
+        }
+
+        self.merge(&other);
+
+        Ok(())
+    }
+
+    /// one record per source file, branches use the keyword column as block
+    /// number, branch 0 is taken and branch 1 is not taken
+    pub fn write_lcov(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "TN:")?;
+
+        for (file, lines) in self.lines() {
+            writeln!(out, "SF:{file}")?;
+
+            let mut found = 0;
+            let mut hit = 0;
+            for (loc, [taken, not_taken]) in self.branches.iter().filter(|(l, _)| l.file() == file)
+            {
+                for (branch, count) in [taken, not_taken].into_iter().enumerate() {
+                    writeln!(
+                        out,
+                        "BRDA:{},{},{},{}",
+                        loc.line(),
+                        loc.column(),
+                        branch,
+                        count
+                    )?;
+
+                    found += 1;
+                    if *count > 0 {
+                        hit += 1;
+                    }
+                }
+            }
+            writeln!(out, "BRF:{found}")?;
+            writeln!(out, "BRH:{hit}")?;
+
+            for (line, hits) in &lines {
+                writeln!(out, "DA:{line},{hits}")?;
+            }
+            writeln!(out, "LF:{}", lines.len())?;
+            writeln!(
+                out,
+                "LH:{}",
+                lines.values().filter(|hits| **hits > 0).count()
+            )?;
+
+            writeln!(out, "end_of_record")?;
+        }
+
+        Ok(())
+    }
+
+    /// source listing prefixed with the hits of every line, `#####` marks
+    /// lines that were never executed
+    pub fn write_listing(&self, out: &mut dyn Write) -> io::Result<()> {
+        for (file, lines) in self.lines() {
+            let source = match fs::read_to_string(file) {
+                Ok(source) => source,
+                Err(err) => {
+                    writeln!(out, "{file}: {err}")?;
+                    continue;
+                }
+            };
+
+            let total = lines.len();
+            let covered = lines.values().filter(|hits| **hits > 0).count();
+            writeln!(out, "{file}: {covered}/{total} lines executed")?;
+
+            for (idx, text) in source.lines().enumerate() {
+                let line = idx + 1;
+
+                let hits = match lines.get(&line) {
+                    Some(0) => "#####".to_string(),
+                    Some(hits) => hits.to_string(),
+                    None => "-".to_string(),
+                };
+
+                write!(out, "{hits:>9}: {line:>5}: {text}")?;
+
+                for (_, [taken, not_taken]) in self.line_branches(file, line) {
+                    write!(out, "  [taken {taken}, not taken {not_taken}]")?;
+                }
+
+                writeln!(out)?;
+            }
+
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn is_branch(op: &Op) -> bool {
+    matches!(op, Op::JEQ(_) | Op::JNE(_) | Op::JLT(_) | Op::JGT(_))
+}
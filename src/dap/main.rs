@@ -0,0 +1,580 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, TryRecvError};
+
+use serde_json::{json, Value};
+
+use dis::lexer::Location;
+use dis::protocol::{read_message, write_message, Malformed};
+use dis::statement::Statement;
+use dis::{diagnostic, error};
+use dis::{stdlib, DIS};
+
+/// steps run between looking for requests like `pause`
+const STEPS_PER_CHECK: usize = 10_000;
+
+const REGISTERS: i64 = 1;
+const FLAGS: i64 = 2;
+const MEMORY: i64 = 3;
+const CALL_STACK: i64 = 4;
+/// memory rows are `MEMORY_ROWS + row`
+const MEMORY_ROWS: i64 = 1000;
+const ROW_WORDS: usize = 16;
+
+/// why execution stopped, as DAP names it
+enum Stop {
+    Step,
+    Breakpoint,
+    Pause,
+    Exited,
+}
+
+/// what `continue` and the steps run until
+#[derive(Clone, Copy)]
+enum Resume {
+    Continue,
+    /// until the return stack is back to this depth
+    Over(usize),
+    /// until the return stack is below this depth
+    Out(usize),
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// where a statement shows in the editor
+fn location(statement: &Statement) -> Option<&Location> {
+    match (statement.op.token(), &statement.label) {
+        (Some(token), _) => Some(token.loc()),
+        (None, Some(label)) => Some(label.loc()),
+        (None, None) => None,
+    }
+}
+
+/// DAP source of a file, bundled files are sent by reference
+fn source(file: &str) -> Value {
+    let name = Path::new(file)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    match file.strip_prefix("<std>/") {
+        Some(std_name) => {
+            let reference = stdlib::names()
+                .position(|n| Some(n) == std_name.strip_suffix(".dis"))
+                .map_or(0, |idx| idx + 1);
+            json!({ "name": name, "sourceReference": reference })
+        }
+        None => json!({ "name": name, "path": canonical(Path::new(file)) }),
+    }
+}
+
+struct Session {
+    seq: i64,
+    dis: Option<DIS>,
+    /// stdin of the program, given at launch
+    input: Vec<u8>,
+    input_pos: usize,
+    /// breakpoint lines by canonical path
+    breakpoint_lines: HashMap<PathBuf, Vec<usize>>,
+    breakpoints: HashSet<usize>,
+    stop_on_entry: bool,
+    running: Option<Resume>,
+    /// statement the program is stopped at, resuming leaves it even when it
+    /// has a breakpoint
+    stopped_at: Option<usize>,
+}
+
+impl Session {
+    fn new() -> Self {
+        Session {
+            seq: 0,
+            dis: None,
+            input: Vec::new(),
+            input_pos: 0,
+            breakpoint_lines: HashMap::new(),
+            breakpoints: HashSet::new(),
+            stop_on_entry: false,
+            running: None,
+            stopped_at: None,
+        }
+    }
+
+    fn send(&mut self, mut message: Value) {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut io::stdout().lock(), &message);
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn output(&mut self, category: &str, text: String) {
+        self.event("output", json!({ "category": category, "output": text }));
+    }
+
+    /// depth of calls and interrupt handlers the program is in
+    fn depth(dis: &DIS) -> usize {
+        dis.return_stack().len() + dis.interrupts.frames.len()
+    }
+
+    /// statements breakpoints at `lines` of `path` stop at, the first one
+    /// starting at or after each line
+    fn resolve(dis: &DIS, path: &Path, lines: &[usize]) -> Vec<Option<(usize, usize)>> {
+        let statements: Vec<(usize, usize)> = dis
+            .program
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, statement)| {
+                let loc = location(statement)?;
+                (canonical(Path::new(loc.file())) == path).then_some((idx, loc.line()))
+            })
+            .collect();
+
+        lines
+            .iter()
+            .map(|line| statements.iter().find(|(_, l)| l >= line).copied())
+            .collect()
+    }
+
+    fn update_breakpoints(&mut self) {
+        self.breakpoints.clear();
+
+        let Some(dis) = &self.dis else {
+            return;
+        };
+
+        for (path, lines) in &self.breakpoint_lines {
+            for (idx, _) in Session::resolve(dis, path, lines).into_iter().flatten() {
+                self.breakpoints.insert(idx);
+            }
+        }
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = args["program"]
+            .as_str()
+            .ok_or("launch needs a `program` path")?;
+
+        let mut dis = DIS::new();
+        if let Some(paths) = args["includePaths"].as_array() {
+            dis.include_paths = paths
+                .iter()
+                .filter_map(|p| Some(PathBuf::from(p.as_str()?)))
+                .collect();
+        }
+
+        let (result, diagnostics) = diagnostic::capture(|| dis.load(program));
+        if result.is_err() {
+            let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+            return Err(messages.join("\n"));
+        }
+
+        self.dis = Some(dis);
+        self.input = args["input"]
+            .as_str()
+            .unwrap_or_default()
+            .as_bytes()
+            .to_vec();
+        self.input_pos = 0;
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.update_breakpoints();
+
+        Ok(Value::Null)
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = args["source"]["path"]
+            .as_str()
+            .ok_or("breakpoints need a source path")?;
+        let path = canonical(Path::new(path));
+
+        let lines: Vec<usize> = args["breakpoints"]
+            .as_array()
+            .map(|breakpoints| {
+                breakpoints
+                    .iter()
+                    .filter_map(|b| b["line"].as_u64())
+                    .map(|line| line as usize)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let resolved = match &self.dis {
+            Some(dis) => Session::resolve(dis, &path, &lines),
+            None => vec![None; lines.len()],
+        };
+
+        let breakpoints: Vec<Value> = lines
+            .iter()
+            .zip(&resolved)
+            .map(|(line, resolved)| match resolved {
+                Some((_, actual)) => json!({ "verified": true, "line": actual }),
+                None => json!({ "verified": false, "line": line }),
+            })
+            .collect();
+
+        self.breakpoint_lines.insert(path, lines);
+        self.update_breakpoints();
+
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn stack_trace(&self) -> Value {
+        let Some(dis) = &self.dis else {
+            return json!({ "stackFrames": [], "totalFrames": 0 });
+        };
+
+        // each frame is in the routine the `run` below it called
+        let mut pcs = vec![dis.pc];
+        pcs.extend(dis.return_stack().iter().rev());
+
+        let mut frames = Vec::new();
+        for (id, &pc) in pcs.iter().enumerate() {
+            let name = match pcs.get(id + 1) {
+                Some(&call) => dis.program[call].target().unwrap_or("?").to_string(),
+                None => "main".to_string(),
+            };
+
+            let frame = match dis.program.get(pc).and_then(location) {
+                Some(loc) => json!({
+                    "id": id,
+                    "name": name,
+                    "source": source(loc.file()),
+                    "line": loc.line(),
+                    "column": loc.column(),
+                }),
+                // past the last statement
+                None => json!({ "id": id, "name": name, "line": 0, "column": 0 }),
+            };
+            frames.push(frame);
+        }
+
+        json!({ "stackFrames": frames, "totalFrames": pcs.len() })
+    }
+
+    fn variables(&self, reference: i64) -> Value {
+        let Some(dis) = &self.dis else {
+            return json!({ "variables": [] });
+        };
+
+        let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+
+        let variables: Vec<Value> = match reference {
+            REGISTERS => dis
+                .config
+                .register_names()
+                .into_iter()
+                .map(|name| {
+                    let value = dis.registers[&name];
+                    variable(format!("#{name}"), format!("{value} (0x{value:x})"))
+                })
+                .collect(),
+            FLAGS => vec![
+                variable("cmp".to_string(), dis.flags()),
+                variable(
+                    "interrupts".to_string(),
+                    match dis.interrupts.enabled {
+                        true => "enabled".to_string(),
+                        false => "disabled".to_string(),
+                    },
+                ),
+                variable("eof".to_string(), dis.eof.to_string()),
+            ],
+            MEMORY => (0..dis.memory.len())
+                .step_by(ROW_WORDS)
+                .map(|start| {
+                    let end = (start + ROW_WORDS).min(dis.memory.len());
+                    let words: Vec<String> = dis.memory[start..end]
+                        .iter()
+                        .map(|word| format!("{word:x}"))
+                        .collect();
+                    json!({
+                        "name": format!("&{start}"),
+                        "value": words.join(" "),
+                        "variablesReference": MEMORY_ROWS + (start / ROW_WORDS) as i64,
+                    })
+                })
+                .collect(),
+            CALL_STACK => dis
+                .return_stack()
+                .iter()
+                .rev()
+                .map(|&call| {
+                    let statement = &dis.program[call];
+                    let at = location(statement).map(|loc| loc.to_string());
+                    variable(
+                        format!("{}", call),
+                        format!("{} at {}", statement.text(), at.unwrap_or_default()),
+                    )
+                })
+                .collect(),
+            row if row >= MEMORY_ROWS => {
+                let start = (row - MEMORY_ROWS) as usize * ROW_WORDS;
+                let end = (start + ROW_WORDS).min(dis.memory.len());
+                (start..end)
+                    .map(|addr| {
+                        let value = dis.memory[addr];
+                        let c = char::from_u32(value)
+                            .filter(|c| !c.is_control())
+                            .map(|c| format!(" '{c}'"))
+                            .unwrap_or_default();
+                        variable(format!("&{addr}"), format!("{value} (0x{value:x}){c}"))
+                    })
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+
+        json!({ "variables": variables })
+    }
+
+    /// runs up to `steps` statements of a resume, `None` while it should
+    /// keep going
+    fn advance(&mut self, steps: usize) -> Option<Stop> {
+        let resume = self.running?;
+        let dis = self.dis.as_mut()?;
+        let leaving = self.stopped_at.take();
+
+        let mut out = Vec::new();
+        let mut stop = None;
+
+        let (_, diagnostics) = diagnostic::capture(|| {
+            for n in 0..steps {
+                if dis.die {
+                    stop = Some(Stop::Exited);
+                    break;
+                }
+
+                // the step that leaves a breakpoint does not stop on it
+                let leaves = n == 0 && leaving == Some(dis.pc);
+                if !leaves && self.breakpoints.contains(&dis.pc) {
+                    stop = Some(Stop::Breakpoint);
+                    break;
+                }
+
+                let mut inp = &self.input[self.input_pos..];
+                let stepped =
+                    panic::catch_unwind(AssertUnwindSafe(|| dis.step(&mut out, &mut inp)));
+                self.input_pos = self.input.len() - inp.len();
+
+                if stepped.is_err() {
+                    error!("runtime error at statement {}", dis.pc);
+                    dis.exit_code = 1;
+                    dis.die = true;
+                }
+
+                if dis.die {
+                    stop = Some(Stop::Exited);
+                    break;
+                }
+
+                let depth = Session::depth(dis);
+                let done = match resume {
+                    Resume::Continue => false,
+                    Resume::Over(d) => depth <= d,
+                    Resume::Out(d) => depth < d,
+                };
+                if done {
+                    stop = Some(Stop::Step);
+                    break;
+                }
+            }
+        });
+
+        if !out.is_empty() {
+            self.output("stdout", String::from_utf8_lossy(&out).to_string());
+        }
+        for diagnostic in diagnostics {
+            self.output("stderr", format!("{diagnostic}\n"));
+        }
+
+        stop
+    }
+
+    fn stopped(&mut self, stop: Stop) {
+        self.running = None;
+        self.stopped_at = self.dis.as_ref().map(|dis| dis.pc);
+
+        let reason = match stop {
+            Stop::Step => "step",
+            Stop::Breakpoint => "breakpoint",
+            Stop::Pause => "pause",
+            Stop::Exited => {
+                let code = self.dis.as_ref().map_or(0, |dis| dis.exit_code);
+                self.event("exited", json!({ "exitCode": code }));
+                self.event("terminated", json!({}));
+                return;
+            }
+        };
+
+        self.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": 1, "allThreadsStopped": true }),
+        );
+    }
+
+    fn resume(&mut self, resume: Resume) {
+        self.running = Some(resume);
+        if let Some(stop) = self.advance(STEPS_PER_CHECK) {
+            self.stopped(stop);
+        }
+    }
+
+    /// answers a message that could not be parsed when its `seq` can be
+    /// found, the session goes on
+    fn malformed(&mut self, malformed: &Malformed) {
+        eprintln!("dis-dap: malformed message: {}", malformed.error);
+
+        let Some(seq) = malformed.field("seq") else {
+            return;
+        };
+
+        let command = malformed.field("command").unwrap_or(json!(""));
+        self.send(json!({
+            "type": "response",
+            "request_seq": seq,
+            "command": command,
+            "success": false,
+            "message": format!("malformed message: {}", malformed.error),
+        }));
+    }
+
+    /// handles a request, false once the session is over
+    fn request(&mut self, message: &Value) -> bool {
+        let command = message["command"].as_str().unwrap_or_default();
+        let args = &message["arguments"];
+        let depth = self.dis.as_ref().map_or(0, Session::depth);
+
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "configurationDone" | "threads" | "scopes" | "stackTrace" | "variables" | "source"
+            | "pause" | "disconnect" | "terminate" => Ok(Value::Null),
+            "continue" | "next" | "stepIn" | "stepOut" if self.dis.is_none() => {
+                Err("no program is running".to_string())
+            }
+            "continue" | "next" | "stepIn" | "stepOut" => Ok(Value::Null),
+            _ => Err(format!("unknown command `{command}`")),
+        };
+
+        let body = match (command, &result) {
+            ("threads", _) => json!({ "threads": [{ "id": 1, "name": "main" }] }),
+            ("stackTrace", _) => self.stack_trace(),
+            ("scopes", _) => json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Flags", "variablesReference": FLAGS, "expensive": false },
+                { "name": "Memory", "variablesReference": MEMORY, "expensive": true },
+                { "name": "Call stack", "variablesReference": CALL_STACK, "expensive": false },
+            ] }),
+            ("variables", _) => self.variables(args["variablesReference"].as_i64().unwrap_or(0)),
+            ("source", _) => {
+                let reference = args["sourceReference"].as_u64().unwrap_or(0) as usize;
+                let content = stdlib::names()
+                    .nth(reference.wrapping_sub(1))
+                    .and_then(stdlib::source)
+                    .unwrap_or_default();
+                json!({ "content": content })
+            }
+            ("continue", Ok(_)) => json!({ "allThreadsContinued": true }),
+            (_, Ok(body)) => body.clone(),
+            (_, Err(_)) => Value::Null,
+        };
+
+        let success = result.is_ok();
+        let mut response = json!({
+            "type": "response",
+            "request_seq": message["seq"],
+            "command": command,
+            "success": success,
+            "body": body,
+        });
+        if let Err(error) = result {
+            response["message"] = json!(error);
+        }
+        self.send(response);
+
+        if !success {
+            return true;
+        }
+
+        match command {
+            // ready for breakpoints once the program is loaded
+            "launch" => self.event("initialized", json!({})),
+            "configurationDone" => match self.stop_on_entry {
+                true => self.stopped(Stop::Step),
+                false => self.resume(Resume::Continue),
+            },
+            "continue" => self.resume(Resume::Continue),
+            "next" => self.resume(Resume::Over(depth)),
+            "stepIn" => self.resume(Resume::Over(usize::MAX)),
+            "stepOut" => self.resume(Resume::Out(depth)),
+            "pause" if self.running.is_some() => self.stopped(Stop::Pause),
+            "disconnect" | "terminate" => {
+                self.event("terminated", json!({}));
+                return false;
+            }
+            _ => {}
+        }
+
+        true
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() > 1 {
+        println!("Usage: {}", args[0]);
+        println!("Debug adapter for DIS, speaks DAP over stdin and stdout");
+        return ExitCode::FAILURE;
+    }
+
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut input = io::stdin().lock();
+        while let Some(message) = read_message(&mut input) {
+            if tx.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = Session::new();
+    loop {
+        let message = match session.running {
+            Some(_) => match rx.try_recv() {
+                Ok(message) => Some(message),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            },
+            None => match rx.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            },
+        };
+
+        match message {
+            Some(Ok(message)) if message["type"] == "request" && !session.request(&message) => {
+                break
+            }
+            Some(Err(malformed)) => session.malformed(&malformed),
+            _ => {}
+        }
+
+        if let Some(stop) = session.advance(STEPS_PER_CHECK) {
+            session.stopped(stop);
+        }
+    }
+
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::Word;
+
+/// Hardware mapped into memory, see `DIS::attach_device`.
+///
+/// Reads and writes of the claimed addresses go to the device instead of
+/// memory, `offset` is relative to the address it was attached at.
+pub trait Device {
+    fn name(&self) -> &str;
+
+    /// number of words claimed
+    fn size(&self) -> usize;
+
+    fn read(&mut self, offset: usize) -> Word;
+
+    fn write(&mut self, offset: usize, value: Word);
+
+    /// writes what the device printed since the last call, `DIS::step`
+    /// passes the `out` it writes the program's output to
+    fn flush(&mut self, _out: &mut dyn Write) {}
+}
+
+/// `+0` write: print value as char, `+1` write: print value as number
+#[derive(Default)]
+pub struct Console {
+    /// printed during the current step
+    pending: Vec<u8>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Device for Console {
+    fn name(&self) -> &str {
+        "console"
+    }
+
+    fn size(&self) -> usize {
+        2
+    }
+
+    fn read(&mut self, _offset: usize) -> Word {
+        0
+    }
+
+    fn write(&mut self, offset: usize, value: Word) {
+        match offset {
+            0 => {
+                let c = char::from_u32(value).unwrap_or(char::REPLACEMENT_CHARACTER);
+                write!(self.pending, "{c}").expect("write error");
+            }
+            _ => write!(self.pending, "{value}").expect("write error"),
+        }
+    }
+
+    fn flush(&mut self, out: &mut dyn Write) {
+        out.write_all(&self.pending).expect("write error");
+        self.pending.clear();
+    }
+}
+
+/// `+0` read: milliseconds, `+1` read: seconds since attached or last write
+pub struct Timer {
+    start: Instant,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Timer {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Timer {
+    fn name(&self) -> &str {
+        "timer"
+    }
+
+    fn size(&self) -> usize {
+        2
+    }
+
+    fn read(&mut self, offset: usize) -> Word {
+        let elapsed = self.start.elapsed();
+        match offset {
+            0 => elapsed.as_millis() as Word,
+            _ => elapsed.as_secs() as Word,
+        }
+    }
+
+    fn write(&mut self, _offset: usize, _value: Word) {
+        self.start = Instant::now();
+    }
+}
+
+/// `+0` read: next pseudo random value, write: reseed
+pub struct Random {
+    state: u32,
+}
+
+impl Random {
+    pub fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.subsec_nanos())
+            .unwrap_or(0);
+
+        Random::with_seed(seed)
+    }
+
+    pub fn with_seed(seed: u32) -> Self {
+        // xorshift32 gets stuck on zero
+        Random { state: seed | 1 }
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Random {
+    fn name(&self) -> &str {
+        "random"
+    }
+
+    fn size(&self) -> usize {
+        1
+    }
+
+    fn read(&mut self, _offset: usize) -> Word {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    fn write(&mut self, _offset: usize, value: Word) {
+        *self = Random::with_seed(value);
+    }
+}
+
+/// Key presses queued by the host.
+///
+/// `+0` read: next key (0 when empty), `+1` read: number of queued keys,
+/// write: clear the queue
+pub struct Keyboard {
+    keys: Arc<Mutex<VecDeque<Word>>>,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Keyboard {
+            keys: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// handle for the host to push keys into, keep it before attaching
+    pub fn keys(&self) -> Arc<Mutex<VecDeque<Word>>> {
+        self.keys.clone()
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Keyboard {
+    fn name(&self) -> &str {
+        "keyboard"
+    }
+
+    fn size(&self) -> usize {
+        2
+    }
+
+    fn read(&mut self, offset: usize) -> Word {
+        let mut keys = self.keys.lock().unwrap();
+        match offset {
+            0 => keys.pop_front().unwrap_or(0),
+            _ => keys.len() as Word,
+        }
+    }
+
+    fn write(&mut self, _offset: usize, _value: Word) {
+        self.keys.lock().unwrap().clear();
+    }
+}
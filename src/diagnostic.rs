@@ -0,0 +1,64 @@
+use std::cell::RefCell;
+use std::fmt;
+
+use crate::lexer::Location;
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub loc: Option<Location>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.loc {
+            Some(loc) => write!(f, "{}: {}", loc, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Vec<Diagnostic>>> = const { RefCell::new(None) };
+}
+
+/// prints the diagnostic to stderr, or keeps it when called inside `capture`
+pub fn emit(diagnostic: Diagnostic) {
+    CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(diagnostics) => diagnostics.push(diagnostic),
+        None => eprintln!("{diagnostic}"),
+    });
+}
+
+/// runs `f`, collecting the diagnostics it reports instead of printing them
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Vec<Diagnostic>) {
+    let outer = CAPTURED.with(|captured| captured.borrow_mut().replace(Vec::new()));
+
+    let result = f();
+
+    let diagnostics = CAPTURED.with(|captured| {
+        let mut captured = captured.borrow_mut();
+        let diagnostics = captured.take().unwrap_or_default();
+        *captured = outer;
+        diagnostics
+    });
+
+    (result, diagnostics)
+}
+
+/// `error!(loc, "format", args..)` or `error!("format", args..)`
+#[macro_export]
+macro_rules! error {
+    ($fmt:literal $($arg:tt)*) => {
+        $crate::diagnostic::emit($crate::diagnostic::Diagnostic {
+            loc: None,
+            message: format!($fmt $($arg)*),
+        })
+    };
+    ($loc:expr, $($arg:tt)*) => {
+        $crate::diagnostic::emit($crate::diagnostic::Diagnostic {
+            loc: Some(::std::clone::Clone::clone($loc)),
+            message: format!($($arg)*),
+        })
+    };
+}
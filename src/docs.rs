@@ -0,0 +1,45 @@
+/// mnemonic, operand types in the README notation and what it does
+pub const INSTRUCTIONS: [(&str, &str, &str); 34] = [
+    ("mov", "mov <NUM | REG | MEM | CHR> <REG | MEM>", "copy the first operand into the second"),
+    ("add", "add <NUM | REG | MEM | CHR> <REG | MEM>", "add the first operand to the second"),
+    ("sub", "sub <NUM | REG | MEM | CHR> <REG | MEM>", "subtract the first operand from the second"),
+    ("cmp", "cmp <NUM | REG | MEM | CHR> <REG | MEM>", "compare the operands and set the `><=` bits for the jumps"),
+    ("jlt", "jlt <LBL>", "jump to the label if the `<` bit is set"),
+    ("jgt", "jgt <LBL>", "jump to the label if the `>` bit is set"),
+    ("jeq", "jeq <LBL>", "jump to the label if the `=` bit is set"),
+    ("jne", "jne <LBL>", "jump to the label if the `=` bit is not set"),
+    ("jmp", "jmp <LBL>", "jump to the label"),
+    ("run", "run <LBL>", "push the next statement to the return stack and jump to the label"),
+    ("ret", "ret", "pop an address from the return stack and jump to it"),
+    ("die", "die [NUM | REG | MEM | CHR]", "end the program, with exit code 0 or the operand"),
+    ("out", "out <NUM | REG | MEM | CHR>", "print the operand as a char"),
+    ("prt", "prt <NUM | REG | MEM | CHR> [w<N> | z<N>]", "print the operand as a number, padded with spaces (`w`) or zeros (`z`)"),
+    ("prx", "prx <NUM | REG | MEM | CHR> [w<N> | z<N>]", "print the operand in hex"),
+    ("prb", "prb <NUM | REG | MEM | CHR> [w<N> | z<N>]", "print the operand in binary"),
+    ("prs", "prs <NUM | REG | MEM | CHR> [w<N> | z<N>]", "print the operand as a signed (two's complement) number"),
+    ("ots", "ots <MEM>", "print the null terminated string starting at the address"),
+    ("len", "len <MEM> <REG | MEM>", "length of the null terminated string starting at the first address"),
+    ("cpy", "cpy <MEM> <MEM> <NUM | REG | MEM>", "copy words from the first address to the second, the ranges may overlap"),
+    ("cmps", "cmps <MEM> <MEM>", "compare two null terminated strings like `cmp` compares values"),
+    ("@", "@ <filename>", "include `<filename>.dis` once, `std/` names are the bundled standard library"),
+    ("export", "export <LBL>", "let files including this one use the label as `<file>.<label>`"),
+    ("rdn", "rdn <MEM | REG>", "read a number from stdin, sets #e (1 when not a number, 2 at end of input)"),
+    ("rdc", "rdc <MEM | REG>", "read a line from stdin and keep its first char, sets #e (1 when empty, 2 at end of input)"),
+    ("rln", "rln <MEM> <NUM | REG | MEM>", "read a line to the address, at most NUM chars (0 for all), count on #3, sets #e (2 at end of input)"),
+    ("rdk", "rdk <MEM | REG>", "read one char from stdin without waiting, sets #e (1 when there is none yet or it can't be stored, 2 at end of input)"),
+    ("dbg", "dbg <NUM | REG | MEM | CHR>", "print the operand and where it came from"),
+    ("sys", "sys <NUM | REG | MEM>", "call a host function: 0 exit with #0, 1 unix time on #0 #1, 2 random value on #0"),
+    ("ivt", "ivt <NUM> <LBL>", "set the handler for an interrupt: 0 timer, 1 input available"),
+    ("ei", "ei", "enable interrupts"),
+    ("di", "di", "disable interrupts"),
+    ("iret", "iret", "return from an interrupt handler, restoring the last `cmp`"),
+    ("tmr", "tmr <NUM | REG | MEM>", "raise interrupt 0 every NUM steps, 0 stops the timer"),
+];
+
+/// operand types and what the instruction does
+pub fn instruction(mnemonic: &str) -> Option<(&'static str, &'static str)> {
+    INSTRUCTIONS
+        .iter()
+        .find(|(name, _, _)| *name == mnemonic)
+        .map(|(_, syntax, summary)| (*syntax, *summary))
+}
@@ -0,0 +1,267 @@
+use crate::lexer::{Lexer, Token};
+use crate::statement::Statement;
+use crate::Result;
+
+/// A line of formatted output.
+enum Line {
+    Blank,
+    Comment(String),
+    Label {
+        name: String,
+        comment: Option<String>,
+    },
+    Code {
+        mnemonic: String,
+        operands: Vec<String>,
+        comment: Option<String>,
+    },
+}
+
+impl Line {
+    fn set_comment(&mut self, text: String) -> bool {
+        match self {
+            Line::Label { comment, .. } | Line::Code { comment, .. } => {
+                *comment = Some(text);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Formats a program, keeping its tokens and comments.
+///
+/// Labels get a line of their own, runs of instructions have their operands
+/// and trailing comments aligned in columns and blank lines are collapsed to
+/// one.
+pub fn format_source(path: &str, source: &str) -> Result<String> {
+    let tokens = Lexer::from_source(path.to_string(), source.to_string())
+        .comments(true)
+        .tokens()?;
+
+    let (mut comments, code): (Vec<Token>, Vec<Token>) = tokens
+        .into_iter()
+        .partition(|token| matches!(token, Token::Comment { .. }));
+    comments.reverse();
+
+    let mut lines = Vec::new();
+    // source line of the last token written out
+    let mut last_line = 0;
+
+    let mut rest = code.clone();
+    let mut start = 0;
+    while !rest.is_empty() {
+        let before = rest.len();
+        Statement::parse(&mut rest)?;
+        let group = &code[start..start + before - rest.len()];
+        start += before - rest.len();
+
+        let (label, instruction) = match &group[0] {
+            Token::Label { .. } => (Some(&group[0]), &group[1..]),
+            _ => (None, group),
+        };
+
+        let first = label.unwrap_or(&group[0]);
+        push_comments(&mut lines, &mut comments, &mut last_line, first);
+
+        if let Some(label) = label {
+            push_blank(&mut lines, last_line, label.loc().line());
+            lines.push(Line::Label {
+                name: label.text(),
+                comment: None,
+            });
+            last_line = label.loc().line();
+        }
+
+        if let Some((mnemonic, operands)) = instruction.split_first() {
+            push_comments(&mut lines, &mut comments, &mut last_line, mnemonic);
+
+            // an instruction stays right below its label
+            if !matches!(lines.last(), Some(Line::Label { .. })) {
+                push_blank(&mut lines, last_line, mnemonic.loc().line());
+            }
+
+            lines.push(Line::Code {
+                mnemonic: mnemonic.text(),
+                operands: operands.iter().map(Token::text).collect(),
+                comment: None,
+            });
+            last_line = group.last().unwrap().loc().line();
+        }
+    }
+
+    while let Some(comment) = comments.pop() {
+        push_comment(&mut lines, &mut last_line, comment);
+    }
+
+    let formatted = render(&lines);
+    check_tokens(path, source, &formatted)?;
+
+    Ok(formatted)
+}
+
+/// writes out the comments that come before `next`
+fn push_comments(
+    lines: &mut Vec<Line>,
+    comments: &mut Vec<Token>,
+    last_line: &mut usize,
+    next: &Token,
+) {
+    while comments
+        .last()
+        .is_some_and(|comment| comment.loc() < next.loc())
+    {
+        let comment = comments.pop().unwrap();
+        push_comment(lines, last_line, comment);
+    }
+}
+
+fn push_comment(lines: &mut Vec<Line>, last_line: &mut usize, comment: Token) {
+    let line = comment.loc().line();
+    let text = comment.text();
+
+    // after code on the same line
+    if line == *last_line && !lines.is_empty() {
+        if let Some(last) = lines.last_mut() {
+            if last.set_comment(text.clone()) {
+                return;
+            }
+        }
+    }
+
+    push_blank(lines, *last_line, line);
+    lines.push(Line::Comment(text));
+    *last_line = line;
+}
+
+/// keeps one blank line where the source had any
+fn push_blank(lines: &mut Vec<Line>, last_line: usize, line: usize) {
+    if line > last_line + 1 && !matches!(lines.last(), None | Some(Line::Blank)) {
+        lines.push(Line::Blank);
+    }
+}
+
+fn render(lines: &[Line]) -> String {
+    let mut out = String::new();
+
+    let mut idx = 0;
+    while idx < lines.len() {
+        match &lines[idx] {
+            Line::Blank => out.push('\n'),
+            Line::Comment(text) => {
+                out.push_str(text);
+                out.push('\n');
+            }
+            Line::Label { name, comment } => {
+                out.push_str(name);
+                if let Some(comment) = comment {
+                    out.push(' ');
+                    out.push_str(comment);
+                }
+                out.push('\n');
+            }
+            Line::Code { .. } => {
+                let end = lines[idx..]
+                    .iter()
+                    .position(|line| !matches!(line, Line::Code { .. }))
+                    .map_or(lines.len(), |len| idx + len);
+
+                render_block(&lines[idx..end], &mut out);
+                idx = end;
+                continue;
+            }
+        }
+
+        idx += 1;
+    }
+
+    out
+}
+
+/// instructions in a row, aligned in columns
+fn render_block(block: &[Line], out: &mut String) {
+    let mut widths: Vec<usize> = Vec::new();
+    for line in block {
+        let Line::Code {
+            mnemonic, operands, ..
+        } = line
+        else {
+            continue;
+        };
+
+        // includes aren't instructions, they keep single spaces
+        if mnemonic == "@" {
+            continue;
+        }
+
+        // the last column is never padded
+        let columns = std::iter::once(mnemonic).chain(operands);
+        for (column, text) in columns.take(operands.len()).enumerate() {
+            if widths.len() <= column {
+                widths.push(0);
+            }
+            widths[column] = widths[column].max(text.chars().count());
+        }
+    }
+
+    let mut rendered = Vec::new();
+    for line in block {
+        let Line::Code {
+            mnemonic,
+            operands,
+            comment,
+        } = line
+        else {
+            continue;
+        };
+
+        let mut text = String::new();
+        let columns: Vec<&String> = std::iter::once(mnemonic).chain(operands).collect();
+        for (column, part) in columns.iter().enumerate() {
+            text.push_str(part);
+            if column + 1 < columns.len() {
+                let pad = match mnemonic.as_str() {
+                    "@" => 0,
+                    _ => widths[column] - part.chars().count(),
+                };
+                text.push_str(&" ".repeat(pad + 1));
+            }
+        }
+
+        rendered.push((text, comment));
+    }
+
+    let comment_column = rendered
+        .iter()
+        .filter(|(_, comment)| comment.is_some())
+        .map(|(text, _)| text.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    for (text, comment) in rendered {
+        out.push_str(&text);
+        if let Some(comment) = comment {
+            let pad = comment_column - text.chars().count();
+            out.push_str(&" ".repeat(pad + 1));
+            out.push_str(comment);
+        }
+        out.push('\n');
+    }
+}
+
+/// formatting must not change what the program means
+fn check_tokens(path: &str, source: &str, formatted: &str) -> Result<()> {
+    let lex = |source: &str| {
+        Lexer::from_source(path.to_string(), source.to_string())
+            .comments(true)
+            .tokens()
+            .map(|tokens| tokens.iter().map(Token::text).collect::<Vec<String>>())
+    };
+
+    if lex(source)? != lex(formatted)? {
+        error!("formatting `{path}` would change its tokens");
+        return Err(());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,266 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::config::Word;
+use crate::{Result, DIS};
+
+pub type Rgb = [u8; 3];
+
+/// Colors picked by the value of a framebuffer cell, wrapping around when
+/// the value is larger than the palette.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: Vec<Rgb>,
+}
+
+impl Palette {
+    /// 16 colors, `0` is black and `1` is white
+    pub fn c64() -> Self {
+        Palette::from_hex(&[
+            0x000000, 0xffffff, 0x880000, 0xaaffee, 0xcc44cc, 0x00cc55, 0x0000aa, 0xeeee77,
+            0xdd8855, 0x664400, 0xff7777, 0x333333, 0x777777, 0xaaff66, 0x0088ff, 0xbbbbbb,
+        ])
+    }
+
+    pub fn mono() -> Self {
+        Palette::from_hex(&[0x000000, 0xffffff])
+    }
+
+    /// 256 shades from black to white
+    pub fn gray() -> Self {
+        Palette {
+            colors: (0..=255).map(|v| [v, v, v]).collect(),
+        }
+    }
+
+    fn from_hex(colors: &[u32]) -> Self {
+        Palette {
+            colors: colors
+                .iter()
+                .map(|c| [(c >> 16) as u8, (c >> 8) as u8, *c as u8])
+                .collect(),
+        }
+    }
+
+    /// `c64`, `mono`, `gray` or a comma separated list like `000000,ff0000`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "c64" => Some(Palette::c64()),
+            "mono" => Some(Palette::mono()),
+            "gray" => Some(Palette::gray()),
+            _ => {
+                let colors = s
+                    .split(',')
+                    .map(|c| {
+                        let c = c.trim().trim_start_matches('#');
+                        if c.len() != 6 {
+                            return None;
+                        }
+                        u32::from_str_radix(c, 16).ok()
+                    })
+                    .collect::<Option<Vec<u32>>>()?;
+
+                Some(Palette::from_hex(&colors))
+            }
+        }
+    }
+
+    pub fn color(&self, value: Word) -> Rgb {
+        self.colors[value as usize % self.colors.len()]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::c64()
+    }
+}
+
+/// Memory region drawn as pixels, one word per pixel in row-major order.
+///
+/// Shared by the `vis` framebuffer pane and `dis --dump-fb` so both show a
+/// program the same way.
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    pub base: usize,
+    pub width: usize,
+    pub height: usize,
+    pub palette: Palette,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Framebuffer {
+            base: 1024,
+            width: 64,
+            height: 32,
+            palette: Palette::default(),
+        }
+    }
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn base(mut self, addr: usize) -> Self {
+        self.base = addr;
+        self
+    }
+
+    pub fn size(mut self, width: usize, height: usize) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    /// `64x32` style sizes
+    pub fn parse_size(s: &str) -> Option<(usize, usize)> {
+        let (width, height) = s.split_once('x')?;
+        Some((width.parse().ok()?, height.parse().ok()?))
+    }
+
+    pub fn validate(&self, dis: &DIS) -> Result<()> {
+        if self.width == 0 || self.height == 0 || self.palette.colors.is_empty() {
+            error!("framebuffer needs at least one pixel and one color");
+            return Err(());
+        }
+
+        let end = self
+            .width
+            .checked_mul(self.height)
+            .and_then(|size| self.base.checked_add(size));
+
+        match end {
+            Some(end) if end <= dis.memory.len() => {}
+            Some(end) => {
+                error!(
+                    "framebuffer at {}-{} does not fit in memory ({} words)",
+                    self.base,
+                    end - 1,
+                    dis.memory.len()
+                );
+                return Err(());
+            }
+            None => {
+                error!(
+                    "framebuffer at {} of {}x{} does not fit in memory ({} words)",
+                    self.base,
+                    self.width,
+                    self.height,
+                    dis.memory.len()
+                );
+                return Err(());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// colors of every pixel, row by row
+    pub fn pixels(&self, dis: &DIS) -> Vec<Rgb> {
+        dis.memory[self.base..self.base + self.width * self.height]
+            .iter()
+            .map(|value| self.palette.color(*value))
+            .collect()
+    }
+
+    /// binary PPM (P6)
+    pub fn write_ppm(&self, dis: &DIS, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for pixel in self.pixels(dis) {
+            out.write_all(&pixel)?;
+        }
+        Ok(())
+    }
+
+    /// 8-bit RGB PNG, the image data is stored without compression
+    pub fn write_png(&self, dis: &DIS, out: &mut dyn Write) -> io::Result<()> {
+        let mut raw = Vec::with_capacity((self.width * 3 + 1) * self.height);
+        for row in self.pixels(dis).chunks(self.width) {
+            // filter type: none
+            raw.push(0);
+            raw.extend(row.iter().flatten());
+        }
+
+        let mut ihdr = Vec::new();
+        ihdr.extend((self.width as u32).to_be_bytes());
+        ihdr.extend((self.height as u32).to_be_bytes());
+        // bit depth 8, truecolor, default compression, filter and interlace
+        ihdr.extend([8, 2, 0, 0, 0]);
+
+        out.write_all(b"\x89PNG\r\n\x1a\n")?;
+        write_chunk(out, b"IHDR", &ihdr)?;
+        write_chunk(out, b"IDAT", &zlib_stored(&raw))?;
+        write_chunk(out, b"IEND", &[])
+    }
+
+    /// PNG when `path` ends in `.png`, PPM otherwise
+    pub fn dump(&self, dis: &DIS, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        match path.extension().is_some_and(|ext| ext == "png") {
+            true => self.write_png(dis, &mut out)?,
+            false => self.write_ppm(dis, &mut out)?,
+        }
+
+        out.flush()
+    }
+}
+
+fn write_chunk(out: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+
+    let crc = crc32(kind.iter().chain(data));
+    out.write_all(&crc.to_be_bytes())
+}
+
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb88320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+/// zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+
+    let blocks: Vec<&[u8]> = data.chunks(0xffff).collect();
+    for (idx, block) in blocks.iter().enumerate() {
+        let last = idx + 1 == blocks.len();
+        out.push(last as u8);
+        out.extend((block.len() as u16).to_le_bytes());
+        out.extend((!(block.len() as u16)).to_le_bytes());
+        out.extend(*block);
+    }
+
+    if blocks.is_empty() {
+        out.extend([1, 0, 0, 0xff, 0xff]);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend(((b << 16) | a).to_be_bytes());
+
+    out
+}
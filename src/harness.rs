@@ -0,0 +1,234 @@
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use crate::config::Word;
+use crate::diagnostic::{self, Diagnostic};
+use crate::DIS;
+
+pub const DEFAULT_MAX_STEPS: usize = 1_000_000;
+
+/// A program together with the input it is fed and what it should produce.
+///
+/// Expectations come from `foo.in`, `foo.out` and `foo.err` next to
+/// `foo.dis`, or from comments inside it:
+///
+/// ```text
+/// - input: 5
+/// - expect: 5
+/// - expect-error: undefined label `ye`
+/// - exit-code: 3
+/// - max-steps: 100
+/// ```
+///
+/// Every `foo.err` line and `expect-error` must be contained in one of the
+/// reported diagnostics, their paths shortened to the file name.
+#[derive(Debug, Clone)]
+pub struct TestCase {
+    pub path: PathBuf,
+    pub input: String,
+    pub expected_out: Option<String>,
+    pub expected_errors: Vec<String>,
+    pub expected_exit_code: Option<Word>,
+    pub max_steps: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Pass,
+    Fail(Vec<String>),
+}
+
+impl TestCase {
+    /// `None` when the program has no expectations, e.g. files that are only
+    /// meant to be included
+    pub fn from_file(path: &Path, max_steps: usize) -> Option<TestCase> {
+        let source = fs::read_to_string(path).ok()?;
+
+        let mut case = TestCase {
+            path: path.to_path_buf(),
+            input: String::new(),
+            expected_out: None,
+            expected_errors: Vec::new(),
+            expected_exit_code: None,
+            max_steps,
+        };
+
+        for line in source.lines() {
+            let Some(comment) = line.trim_start().strip_prefix('-') else {
+                continue;
+            };
+
+            let Some((directive, value)) = comment.trim_start().split_once(':') else {
+                continue;
+            };
+            let value = value.strip_prefix(' ').unwrap_or(value);
+
+            match directive {
+                "input" => {
+                    case.input.push_str(value);
+                    case.input.push('\n');
+                }
+                "expect" => {
+                    let expected = case.expected_out.get_or_insert_with(String::new);
+                    expected.push_str(value);
+                    expected.push('\n');
+                }
+                "expect-error" => case.expected_errors.push(value.to_string()),
+                "exit-code" => case.expected_exit_code = value.trim().parse().ok(),
+                "max-steps" => {
+                    if let Ok(steps) = value.trim().parse() {
+                        case.max_steps = steps;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Ok(input) = fs::read_to_string(path.with_extension("in")) {
+            case.input.push_str(&input);
+        }
+
+        if let Ok(expected) = fs::read_to_string(path.with_extension("out")) {
+            case.expected_out
+                .get_or_insert_with(String::new)
+                .push_str(&expected);
+        }
+
+        if let Ok(expected) = fs::read_to_string(path.with_extension("err")) {
+            case.expected_errors.extend(
+                expected
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| line.to_string()),
+            );
+        }
+
+        if case.expected_out.is_none()
+            && case.expected_errors.is_empty()
+            && case.expected_exit_code.is_none()
+        {
+            return None;
+        }
+
+        Some(case)
+    }
+
+    pub fn run(&self) -> Outcome {
+        let mut out = Vec::new();
+
+        let (result, diagnostics) = diagnostic::capture(|| {
+            let mut dis = DIS::new();
+            if dis.load(self.path.to_string_lossy()).is_err() {
+                return Ok(None);
+            }
+
+            let mut inp = self.input.as_bytes();
+            let mut steps = 0;
+
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                while !dis.die {
+                    if steps == self.max_steps {
+                        return Err(format!("step limit of {} exceeded", self.max_steps));
+                    }
+
+                    dis.step(&mut out, &mut inp);
+                    steps += 1;
+                }
+
+                Ok(Some(dis.exit_code))
+            }))
+            .unwrap_or_else(|payload| {
+                let message = match payload.downcast_ref::<&str>() {
+                    Some(message) => message.to_string(),
+                    None => match payload.downcast_ref::<String>() {
+                        Some(message) => message.clone(),
+                        None => "unknown panic".to_string(),
+                    },
+                };
+                Err(format!("panicked: {message}"))
+            })
+        });
+
+        let diagnostics: Vec<String> = diagnostics.iter().map(short_diagnostic).collect();
+        let mut failures = Vec::new();
+
+        match result {
+            Err(reason) => failures.push(reason),
+            Ok(None) if self.expected_errors.is_empty() => {
+                failures.push("failed to load".to_string());
+            }
+            Ok(Some(exit_code)) => {
+                let expected = self.expected_exit_code.unwrap_or(0);
+                if exit_code != expected {
+                    failures.push(format!("expected exit code {expected}, got {exit_code}"));
+                }
+            }
+            Ok(None) => {}
+        }
+
+        for expected in &self.expected_errors {
+            if !diagnostics.iter().any(|d| d.contains(expected.as_str())) {
+                failures.push(format!("expected diagnostic: {expected}"));
+            }
+        }
+
+        if self.expected_errors.is_empty() || !failures.is_empty() {
+            failures.extend(diagnostics.iter().map(|d| format!("diagnostic: {d}")));
+        }
+
+        if let Some(expected) = &self.expected_out {
+            let actual = String::from_utf8_lossy(&out);
+            if actual != *expected {
+                failures.push(format!("expected output: {expected:?}"));
+                failures.push(format!("actual output:   {actual:?}"));
+            }
+        }
+
+        match failures.is_empty() {
+            true => Outcome::Pass,
+            false => Outcome::Fail(failures),
+        }
+    }
+}
+
+fn short_diagnostic(diagnostic: &Diagnostic) -> String {
+    match &diagnostic.loc {
+        Some(loc) => {
+            let file = Path::new(loc.file())
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default();
+
+            format!(
+                "{}:{}:{}: {}",
+                file,
+                loc.line(),
+                loc.column(),
+                diagnostic.message
+            )
+        }
+        None => diagnostic.message.clone(),
+    }
+}
+
+/// `.dis` files in `paths`, directories are searched recursively
+pub fn discover(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            let Ok(entries) = fs::read_dir(path) else {
+                continue;
+            };
+
+            let children: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+            found.extend(discover(&children));
+        } else if path.extension().is_some_and(|ext| ext == "dis") {
+            found.push(path.clone());
+        }
+    }
+
+    found.sort();
+    found
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Source files of a program and the files each of them includes.
+///
+/// Files are told apart by their canonical path, so a file included more
+/// than once, under any name, only adds its statements the first time.
+#[derive(Debug, Clone, Default)]
+pub struct IncludeGraph {
+    /// paths as they were resolved, the program file first
+    files: Vec<String>,
+    ids: HashMap<PathBuf, usize>,
+    includes: Vec<Vec<usize>>,
+}
+
+impl IncludeGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// bundled files can't be canonicalized and keep their name
+    fn key(path: &str) -> PathBuf {
+        fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+    }
+
+    pub(crate) fn id(&self, path: &str) -> Option<usize> {
+        self.ids.get(&IncludeGraph::key(path)).copied()
+    }
+
+    pub(crate) fn add_file(&mut self, path: &str) -> usize {
+        if let Some(id) = self.id(path) {
+            return id;
+        }
+
+        let id = self.files.len();
+        self.ids.insert(IncludeGraph::key(path), id);
+        self.files.push(path.to_string());
+        self.includes.push(Vec::new());
+        id
+    }
+
+    pub(crate) fn add_include(&mut self, from: usize, to: usize) {
+        if !self.includes[from].contains(&to) {
+            self.includes[from].push(to);
+        }
+    }
+
+    pub(crate) fn path(&self, id: usize) -> &str {
+        &self.files[id]
+    }
+
+    /// every source file, the program first and then in include order
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// files `file` includes itself
+    pub fn includes(&self, file: &str) -> Vec<&str> {
+        match self.id(file) {
+            Some(id) => self.includes[id].iter().map(|&i| self.path(i)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// `file` and everything it includes, directly or through other files
+    pub fn sources(&self, file: &str) -> Vec<&str> {
+        let Some(id) = self.id(file) else {
+            return Vec::new();
+        };
+
+        let mut seen = vec![id];
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            for &include in &self.includes[id] {
+                if !seen.contains(&include) {
+                    seen.push(include);
+                    stack.push(include);
+                }
+            }
+        }
+
+        seen.sort();
+        seen.into_iter().map(|id| self.path(id)).collect()
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+/// Where `rdn`, `rdc`, `rln` and `rdk` read from.
+pub trait Input: BufRead {
+    /// whether there is input to read without waiting for it
+    fn ready(&mut self) -> bool;
+
+    /// whether the input has ended, without waiting to find out
+    fn eof(&mut self) -> bool;
+}
+
+impl Input for &[u8] {
+    fn ready(&mut self) -> bool {
+        !self.is_empty()
+    }
+
+    fn eof(&mut self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl Input for io::Empty {
+    fn ready(&mut self) -> bool {
+        false
+    }
+
+    fn eof(&mut self) -> bool {
+        true
+    }
+}
+
+/// only counts what is already buffered, filling it could block
+impl<R: Read> Input for BufReader<R> {
+    fn ready(&mut self) -> bool {
+        !self.buffer().is_empty()
+    }
+
+    fn eof(&mut self) -> bool {
+        false
+    }
+}
+
+/// Stdin read on a background thread so `ready` never blocks.
+///
+/// The thread is started by the first read, programs that never read keep
+/// stdin free for devices.
+#[derive(Default)]
+pub struct StdinInput {
+    chunks: Option<Receiver<Vec<u8>>>,
+    buf: Vec<u8>,
+    pos: usize,
+    closed: bool,
+}
+
+impl StdinInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn chunks(&mut self) -> &Receiver<Vec<u8>> {
+        self.chunks.get_or_insert_with(|| {
+            let (tx, rx) = mpsc::channel();
+
+            std::thread::spawn(move || {
+                let mut stdin = io::stdin();
+                let mut chunk = [0; 1024];
+                // dropping `tx` on EOF or error ends the input
+                while let Ok(n @ 1..) = stdin.read(&mut chunk) {
+                    if tx.send(chunk[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            rx
+        })
+    }
+}
+
+impl Read for StdinInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for StdinInput {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.buf.len() {
+            // an empty buffer reads as EOF once the thread is gone
+            match self.chunks().recv() {
+                Ok(chunk) => self.buf = chunk,
+                Err(_) => {
+                    self.buf.clear();
+                    self.closed = true;
+                }
+            }
+            self.pos = 0;
+        }
+
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
+
+impl Input for StdinInput {
+    fn ready(&mut self) -> bool {
+        if self.pos < self.buf.len() {
+            return true;
+        }
+
+        match self.chunks().try_recv() {
+            Ok(chunk) => {
+                self.buf = chunk;
+                self.pos = 0;
+                true
+            }
+            Err(TryRecvError::Empty) => false,
+            Err(TryRecvError::Disconnected) => {
+                self.closed = true;
+                false
+            }
+        }
+    }
+
+    fn eof(&mut self) -> bool {
+        !self.ready() && self.closed
+    }
+}
+
+/// Lines typed into a debugger, reads wait for more instead of finding the
+/// end of the input.
+#[derive(Debug, Default)]
+pub struct Typed {
+    pub bytes: VecDeque<u8>,
+    /// nothing more will be typed, reads past what was typed find the end
+    pub closed: bool,
+}
+
+impl Typed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// queues `line` and its newline
+    pub fn push_line(&mut self, line: &str) {
+        self.bytes.extend(line.bytes());
+        self.bytes.push_back(b'\n');
+    }
+
+    /// whether a whole line is waiting to be read
+    pub fn has_line(&self) -> bool {
+        self.bytes.contains(&b'\n')
+    }
+}
+
+impl Read for Typed {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.bytes.read(buf)
+    }
+}
+
+impl BufRead for Typed {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.bytes.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.bytes.consume(amt)
+    }
+}
+
+impl Input for Typed {
+    fn ready(&mut self) -> bool {
+        !self.bytes.is_empty()
+    }
+
+    fn eof(&mut self) -> bool {
+        self.closed && self.bytes.is_empty()
+    }
+}
+
+/// Reads one UTF-8 encoded char, `None` when the bytes read aren't one.
+pub fn read_char(inp: &mut dyn Input) -> Option<char> {
+    let mut bytes = [0; 4];
+    inp.read_exact(&mut bytes[..1]).ok()?;
+
+    let len = match bytes[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return None,
+    };
+    inp.read_exact(&mut bytes[1..len]).ok()?;
+
+    std::str::from_utf8(&bytes[..len]).ok()?.chars().next()
+}
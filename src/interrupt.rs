@@ -0,0 +1,72 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::config::Word;
+
+/// raised every `tmr <steps>` steps
+pub const TIMER: Word = 0;
+/// raised while there is input to read without waiting
+pub const INPUT: Word = 1;
+
+/// State saved when an interrupt is taken, restored by `iret`.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub interrupt: Word,
+    pub pc: usize,
+    pub cmp: u8,
+}
+
+/// Interrupts are checked between steps, a pending interrupt with a vector
+/// jumps to its handler while they are enabled.
+///
+/// Taking an interrupt disables further ones until `iret`, lower numbers
+/// are taken first. Pending interrupts without a vector are dropped.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupts {
+    /// interrupt number to handler statement, set by `ivt`
+    pub vectors: HashMap<Word, usize>,
+    pub enabled: bool,
+    pub pending: BTreeSet<Word>,
+    pub frames: Vec<Frame>,
+    /// steps between timer interrupts, 0 when stopped
+    pub timer: Word,
+    ticks: Word,
+}
+
+impl Interrupts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_timer(&mut self, steps: Word) {
+        self.timer = steps;
+        self.ticks = 0;
+    }
+
+    /// counts a step, raising `TIMER` when the interval is up
+    pub(crate) fn tick(&mut self) {
+        if self.timer == 0 {
+            return;
+        }
+
+        self.ticks += 1;
+        if self.ticks >= self.timer {
+            self.ticks = 0;
+            self.pending.insert(TIMER);
+        }
+    }
+
+    /// next interrupt to take and its handler
+    pub(crate) fn take(&mut self) -> Option<(Word, usize)> {
+        if !self.enabled {
+            return None;
+        }
+
+        while let Some(interrupt) = self.pending.pop_first() {
+            if let Some(target) = self.vectors.get(&interrupt) {
+                return Some((interrupt, *target));
+            }
+        }
+
+        None
+    }
+}
@@ -1,20 +1,40 @@
 use std::fmt;
 use std::fs;
 
+use crate::config::Word;
 use crate::Result;
 
-const KEYWORDS: [&str; 19] = [
+pub const KEYWORDS: [&str; 34] = [
     "mov", "add", "sub", "cmp", "jmp", "jlt", "jgt", "jeq", "jne", "run", "ret", "die", "out",
-    "prt", "@", "rdn", "rdc", "rln", "dbg",
+    "prt", "@", "rdn", "rdc", "rln", "dbg", "sys", "ivt", "ei", "di", "iret", "tmr", "rdk", "ots",
+    "len", "cpy", "cmps", "prx", "prb", "prs", "export",
 ];
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Location {
     file: String,
     line: usize,
     column: usize,
 }
 
+impl Location {
+    pub fn new(file: String, line: usize, column: usize) -> Location {
+        Location { file, line, column }
+    }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}:{}:{}", self.file, self.line, self.column)
@@ -23,13 +43,39 @@ impl fmt::Display for Location {
 
 #[derive(Debug, Clone)]
 pub enum Token {
-    Keyword { value: String, loc: Location },
-    Label { value: String, loc: Location },
-    Char { value: char, loc: Location },
-    Number { value: u16, loc: Location },
-    Register { value: String, loc: Location },
-    Memory { value: String, loc: Location },
-    Identifier { value: String, loc: Location },
+    Keyword {
+        value: String,
+        loc: Location,
+    },
+    Label {
+        value: String,
+        loc: Location,
+    },
+    Char {
+        value: char,
+        loc: Location,
+    },
+    Number {
+        value: Word,
+        loc: Location,
+    },
+    Register {
+        value: String,
+        loc: Location,
+    },
+    Memory {
+        value: String,
+        loc: Location,
+    },
+    Identifier {
+        value: String,
+        loc: Location,
+    },
+    /// `- text` up to the end of the line, only kept by `Lexer::comments`
+    Comment {
+        value: String,
+        loc: Location,
+    },
 }
 
 impl Token {
@@ -42,6 +88,21 @@ impl Token {
             Token::Register { loc, .. } => loc,
             Token::Memory { loc, .. } => loc,
             Token::Identifier { loc, .. } => loc,
+            Token::Comment { loc, .. } => loc,
+        }
+    }
+
+    /// the token as it is written in source
+    pub fn text(&self) -> String {
+        match self {
+            Token::Keyword { value, .. } => value.clone(),
+            Token::Label { value, .. } => format!("{value}:"),
+            Token::Char { value, .. } => format!(".{value}"),
+            Token::Number { value, .. } => value.to_string(),
+            Token::Register { value, .. } => format!("#{value}"),
+            Token::Memory { value, .. } => format!("&{value}"),
+            Token::Identifier { value, .. } => value.clone(),
+            Token::Comment { value, .. } => value.clone(),
         }
     }
 }
@@ -56,6 +117,7 @@ impl fmt::Display for Token {
             Token::Register { value, .. } => write!(f, "Register({})", value),
             Token::Memory { value, .. } => write!(f, "Memory({})", value),
             Token::Identifier { value, .. } => write!(f, "Identifier({})", value),
+            Token::Comment { value, .. } => write!(f, "Comment({})", value),
         }
     }
 }
@@ -63,7 +125,11 @@ impl fmt::Display for Token {
 pub struct Lexer {
     source_path: String,
     source: String,
+    /// byte offset into `source`
     pos: usize,
+    comments: bool,
+    /// words until the label operand of a jump, `run` or `ivt`
+    label_operand: Option<usize>,
 }
 
 impl Lexer {
@@ -73,23 +139,36 @@ impl Lexer {
         let source = match source {
             Ok(source) => source,
             Err(_) => {
-                eprintln!("Failed to read file: {}", source_path);
+                error!("Failed to read file: {}", source_path);
                 return Err(());
             }
         };
 
-        Ok(Lexer {
+        Ok(Lexer::from_source(source_path, source))
+    }
+
+    /// lexes `source` as if it was read from `source_path`
+    pub fn from_source(source_path: String, source: String) -> Lexer {
+        Lexer {
             source_path: source_path,
             source: source,
             pos: 0,
-        })
+            comments: false,
+            label_operand: None,
+        }
+    }
+
+    /// keep comments as `Token::Comment` instead of skipping them
+    pub fn comments(mut self, comments: bool) -> Lexer {
+        self.comments = comments;
+        self
     }
 
     fn location(&self, pos: usize) -> Location {
         let mut line = 0;
         let mut column = 0;
 
-        for (i, c) in self.source.chars().enumerate() {
+        for (i, c) in self.source.char_indices() {
             if i == pos {
                 break;
             }
@@ -110,9 +189,9 @@ impl Lexer {
     }
 
     fn strip_whitespace(&mut self) {
-        while let Some(c) = self.source.chars().nth(self.pos) {
+        while let Some(c) = self.source[self.pos..].chars().next() {
             if c.is_whitespace() {
-                self.pos += 1;
+                self.pos += c.len_utf8();
             } else {
                 break;
             }
@@ -143,24 +222,49 @@ impl Lexer {
     }
 
     pub fn next_token(&mut self) -> Option<Token> {
+        let token = self.read_token()?;
+
+        self.label_operand = match &token {
+            Token::Comment { .. } => self.label_operand,
+            Token::Keyword { value, .. } => match value.as_str() {
+                "jmp" | "jlt" | "jgt" | "jeq" | "jne" | "run" => Some(0),
+                "ivt" => Some(1),
+                _ => None,
+            },
+            _ => self.label_operand.and_then(|words| words.checked_sub(1)),
+        };
+
+        Some(token)
+    }
+
+    fn read_token(&mut self) -> Option<Token> {
         self.strip_whitespace();
         let pos = self.pos;
         if let Some(word) = self.peek_word() {
             if word.starts_with("-") {
-                while let Some(c) = self.source.chars().nth(self.pos) {
+                while let Some(c) = self.source[self.pos..].chars().next() {
                     if c == '\n' {
                         break;
                     }
-                    self.advance(1);
+                    self.advance(c.len_utf8());
+                }
+
+                if self.comments {
+                    return Some(Token::Comment {
+                        value: self.source[pos..self.pos].trim_end().to_string(),
+                        loc: self.location(pos),
+                    });
                 }
 
-                return self.next_token();
+                return self.read_token();
             }
 
             let loc = self.location(pos);
             self.advance(word.len());
 
-            if word.starts_with(".") {
+            // longer words are local labels, and so is `.a` where a label is
+            // expected
+            if word.starts_with(".") && word.chars().count() == 2 && self.label_operand != Some(0) {
                 return Some(Token::Char {
                     value: word.chars().nth(1).unwrap(),
                     loc: loc,
@@ -1,16 +1,44 @@
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
-use std::io::{BufRead, BufReader, Write};
-
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[macro_use]
+pub mod diagnostic;
+pub mod cfg;
+pub mod config;
+pub mod coverage;
+pub mod device;
+pub mod docs;
+pub mod formatter;
+pub mod framebuffer;
+pub mod harness;
+pub mod include;
+pub mod input;
+pub mod interrupt;
 pub mod lexer;
+pub mod lint;
+pub mod profile;
+#[cfg(feature = "json")]
+pub mod protocol;
+pub mod repl;
 pub mod statement;
-
-use lexer::{Lexer, Token};
+pub mod stdlib;
+pub mod syscall;
+
+use config::{MachineConfig, Word};
+use coverage::Coverage;
+use device::Device;
+use include::IncludeGraph;
+use input::{read_char, Input, StdinInput};
+use interrupt::{Frame, Interrupts};
+use lexer::{Lexer, Location, Token};
+use profile::Profiler;
 use statement::{Op, Statement};
+use syscall::Syscall;
 
 pub type Result<T> = std::result::Result<T, ()>;
 
-const MEM_SIZE: usize = 4096;
-
 enum CMP {
     EQ = 0b001,
     LT = 0b010,
@@ -18,8 +46,9 @@ enum CMP {
 }
 
 pub struct DIS {
-    pub registers: HashMap<String, u16>,
-    pub memory: [u16; MEM_SIZE],
+    pub config: MachineConfig,
+    pub registers: HashMap<String, Word>,
+    pub memory: Vec<Word>,
     return_stack: Vec<usize>,
     label_map: HashMap<String, usize>,
     pub program: Vec<Statement>,
@@ -27,25 +56,170 @@ pub struct DIS {
     pub cmp: u8,
 
     pub die: bool,
+    pub exit_code: Word,
+    /// a read found the end of the input
+    pub eof: bool,
+
+    syscalls: HashMap<Word, Syscall>,
+    devices: Vec<(usize, Box<dyn Device>)>,
+    pub interrupts: Interrupts,
+
+    pub profiler: Option<Profiler>,
+    pub coverage: Option<Coverage>,
+
+    /// searched for `@` includes after the including file's directory and
+    /// before `DIS_PATH`
+    pub include_paths: Vec<PathBuf>,
+    /// files the loaded program is made of
+    pub includes: IncludeGraph,
+    /// labels other files can use, by their program wide name
+    pub exports: HashSet<String>,
+    /// sources read instead of the file at their canonical path, like
+    /// unsaved changes in an editor
+    pub unsaved: HashMap<PathBuf, String>,
 }
 impl DIS {
     pub fn new() -> Self {
-        let mut registers = HashMap::new();
-        for i in 0..8 {
-            registers.insert(i.to_string(), 0);
-            registers.insert("e".to_string(), 0);
-        }
+        Self::new_with(MachineConfig::default()).unwrap()
+    }
 
-        DIS {
+    pub fn new_with(config: MachineConfig) -> Result<Self> {
+        config.validate()?;
+
+        let registers = config
+            .register_names()
+            .into_iter()
+            .map(|name| (name, 0))
+            .collect();
+
+        Ok(DIS {
+            memory: vec![0; config.memory_size],
+            config,
             registers,
-            memory: [0; MEM_SIZE],
             return_stack: Vec::new(),
             label_map: HashMap::new(),
             program: Vec::new(),
             pc: 0,
             cmp: 0,
             die: false,
+            exit_code: 0,
+            eof: false,
+            syscalls: syscall::defaults().into_iter().collect(),
+            devices: Vec::new(),
+            interrupts: Interrupts::new(),
+            profiler: None,
+            coverage: None,
+            include_paths: Vec::new(),
+            includes: IncludeGraph::new(),
+            exports: HashSet::new(),
+            unsaved: HashMap::new(),
+        })
+    }
+
+    /// makes `sys <id>` call `handler`, replacing any previous handler
+    pub fn register_syscall(&mut self, id: Word, handler: Syscall) {
+        self.syscalls.insert(id, handler);
+    }
+
+    /// maps `device` into memory starting at `base`
+    pub fn attach_device(&mut self, base: usize, device: Box<dyn Device>) -> Result<()> {
+        let size = device.size();
+        let end = base.checked_add(size).filter(|_| size > 0);
+
+        let Some(end) = end.filter(|&end| end <= self.memory.len()) else {
+            // no last word to show when it is empty or past the address space
+            let range = match end {
+                Some(end) => format!("{base}-{}", end - 1),
+                None => format!("{base} ({size} words)"),
+            };
+            error!(
+                "device `{}` at {range} does not fit in memory ({} words)",
+                device.name(),
+                self.memory.len()
+            );
+            return Err(());
+        };
+
+        for (other_base, other) in &self.devices {
+            if base < other_base + other.size() && *other_base < end {
+                error!(
+                    "device `{}` at {base}-{} overlaps `{}` at {other_base}-{}",
+                    device.name(),
+                    end - 1,
+                    other.name(),
+                    other_base + other.size() - 1
+                );
+                return Err(());
+            }
         }
+
+        self.devices.push((base, device));
+
+        Ok(())
+    }
+
+    /// marks `interrupt` pending, it is taken before the next step if it has a
+    /// vector and interrupts are enabled
+    pub fn raise(&mut self, interrupt: Word) {
+        self.interrupts.pending.insert(interrupt);
+    }
+
+    pub fn devices(&self) -> impl Iterator<Item = (usize, &dyn Device)> {
+        self.devices
+            .iter()
+            .map(|(base, device)| (*base, device.as_ref()))
+    }
+
+    fn device_at(&mut self, addr: usize) -> Option<(usize, &mut Box<dyn Device>)> {
+        self.devices
+            .iter_mut()
+            .find(|(base, device)| *base <= addr && addr < base + device.size())
+            .map(|(base, device)| (addr - *base, device))
+    }
+
+    /// word at `addr`, an address outside memory is reported at `loc`
+    fn read_memory(&mut self, addr: usize, loc: &Location) -> Result<Word> {
+        let mask = self.config.word_width.mask();
+
+        if let Some((offset, device)) = self.device_at(addr) {
+            return Ok(device.read(offset) & mask);
+        }
+
+        match self.memory.get(addr) {
+            Some(word) => Ok(*word),
+            None => {
+                self.outside_memory(addr, loc);
+                Err(())
+            }
+        }
+    }
+
+    fn write_memory(&mut self, addr: usize, value: Word, loc: &Location) -> Result<()> {
+        let value = value & self.config.word_width.mask();
+
+        if let Some((offset, device)) = self.device_at(addr) {
+            device.write(offset, value);
+            return Ok(());
+        }
+
+        match self.memory.get_mut(addr) {
+            Some(word) => {
+                *word = value;
+                Ok(())
+            }
+            None => {
+                self.outside_memory(addr, loc);
+                Err(())
+            }
+        }
+    }
+
+    fn outside_memory(&self, addr: usize, loc: &Location) {
+        error!(
+            loc,
+            "address &{addr} is outside memory ({} words)",
+            self.memory.len()
+        );
     }
 
     pub fn restart_program(&mut self) {
@@ -55,19 +229,44 @@ impl DIS {
 
         self.pc = 0;
         self.die = false;
+        self.exit_code = 0;
+        self.eof = false;
         self.cmp = 0;
+        self.interrupts = Interrupts::new();
+
+        if let Some(profiler) = &mut self.profiler {
+            *profiler = Profiler::new();
+        }
+    }
+
+    /// statements of the `run`s waiting for their `ret`, innermost last
+    pub fn return_stack(&self) -> &[usize] {
+        &self.return_stack
+    }
+
+    /// bits set by the last comparison as `><=`, unset ones as `-`
+    pub fn flags(&self) -> String {
+        [(CMP::GT, '>'), (CMP::LT, '<'), (CMP::EQ, '=')]
+            .into_iter()
+            .map(|(bit, c)| match self.cmp & bit as u8 {
+                0 => '-',
+                _ => c,
+            })
+            .collect()
     }
 
     fn reset(&mut self) {
         self.restart_program();
         self.program.clear();
+        self.label_map.clear();
+        self.exports.clear();
     }
 
     fn index_labels(&mut self) -> Result<()> {
         for (idx, statement) in self.program.iter().enumerate() {
             if let Some(Token::Label { value, loc }) = &statement.label {
                 if self.label_map.contains_key(value) {
-                    eprintln!("{loc}: duplicate label `{value}`");
+                    error!(loc, "duplicate label `{value}`");
 
                     let first_loc = match &self.program[self.label_map[value]] {
                         Statement {
@@ -77,7 +276,7 @@ impl DIS {
                         _ => unreachable!(),
                     };
 
-                    eprintln!("first defined here: {first_loc}");
+                    error!("first defined here: {first_loc}");
 
                     return Err(());
                 }
@@ -89,6 +288,195 @@ impl DIS {
         Ok(())
     }
 
+    /// Gives every label a program wide name and points references at it.
+    ///
+    /// Labels belong to the file defining them, labels of included files are
+    /// prefixed with the file name (`math.mul`) and other files can only use
+    /// them when they are exported. `.name` labels belong to the label before
+    /// them (`mul.loop`).
+    fn resolve_labels(&mut self) -> Result<()> {
+        let namespaces: HashMap<String, String> = self
+            .includes
+            .files()
+            .iter()
+            .enumerate()
+            .map(|(id, path)| {
+                let namespace = match id {
+                    0 => String::new(),
+                    _ => Path::new(path)
+                        .file_stem()
+                        .unwrap()
+                        .to_string_lossy()
+                        .to_string(),
+                };
+                (path.clone(), namespace)
+            })
+            .collect();
+
+        // files are told apart by their name, two `util.dis` would mix up
+        let mut owners: HashMap<&str, &str> = HashMap::new();
+        for file in self.includes.files() {
+            let namespace = namespaces[file].as_str();
+            if namespace.is_empty() {
+                continue;
+            }
+
+            if let Some(other) = owners.insert(namespace, file) {
+                error!("namespace `{namespace}` is used by both {other} and {file}");
+                return Err(());
+            }
+        }
+
+        let qualify = |namespace: &str, name: &str| match namespace {
+            "" => name.to_string(),
+            _ => format!("{namespace}.{name}"),
+        };
+
+        let mut valid = true;
+
+        // labels of every file by their name inside it, and the label each
+        // statement's `.name` references belong to
+        let mut defined: HashMap<String, HashSet<String>> = HashMap::new();
+        let mut exports: HashMap<String, Vec<Token>> = HashMap::new();
+        let mut scopes: Vec<Option<String>> = Vec::new();
+        let mut current: HashMap<String, String> = HashMap::new();
+
+        for statement in &mut self.program {
+            let file = statement.file().to_string();
+
+            if let Some(Token::Label { value, loc }) = &mut statement.label {
+                let name = match value.starts_with('.') {
+                    true => match current.get(&file) {
+                        Some(global) => format!("{global}{value}"),
+                        None => {
+                            error!(loc, "local label `{value}` has no label before it");
+                            valid = false;
+                            value.clone()
+                        }
+                    },
+                    false => {
+                        current.insert(file.clone(), value.clone());
+                        value.clone()
+                    }
+                };
+
+                *value = qualify(&namespaces[&file], &name);
+                defined.entry(file.clone()).or_default().insert(name);
+            }
+
+            scopes.push(current.get(&file).cloned());
+
+            if let Op::EXPORT(_) = statement.op {
+                exports
+                    .entry(file)
+                    .or_default()
+                    .push(statement.body[0].clone());
+            }
+        }
+
+        let mut exported: HashMap<&str, HashSet<&str>> = HashMap::new();
+        for (file, names) in &exports {
+            for name in names {
+                let Token::Identifier { value, loc } = name else {
+                    unreachable!()
+                };
+
+                if value.starts_with('.') || !defined.get(file).is_some_and(|d| d.contains(value)) {
+                    error!(loc, "exported label `{value}` is not defined");
+                    valid = false;
+                }
+
+                exported.entry(file).or_default().insert(value);
+                self.exports.insert(qualify(&namespaces[file], value));
+            }
+        }
+
+        for (idx, statement) in self.program.iter_mut().enumerate() {
+            let Some(operand) = statement.target_operand() else {
+                continue;
+            };
+
+            let file = statement.file().to_string();
+            let Token::Identifier { value, loc } = &mut statement.body[operand] else {
+                unreachable!()
+            };
+
+            let name = match (value.starts_with('.'), &scopes[idx]) {
+                (true, Some(global)) => format!("{global}{value}"),
+                _ => value.clone(),
+            };
+
+            if defined.get(&file).is_some_and(|d| d.contains(&name)) {
+                *value = qualify(&namespaces[&file], &name);
+                continue;
+            }
+
+            // `math.mul` is `mul` exported by math.dis
+            if let Some((namespace, label)) = name.split_once('.') {
+                let other = namespaces.iter().find(|(other, other_namespace)| {
+                    **other != file && *other_namespace == namespace
+                });
+
+                if let Some((other, _)) = other {
+                    if exported
+                        .get(other.as_str())
+                        .is_some_and(|e| e.contains(label))
+                    {
+                        *value = qualify(namespace, label);
+                        continue;
+                    }
+
+                    if defined.get(other).is_some_and(|d| d.contains(label)) {
+                        error!(loc, "label `{label}` is not exported by {other}");
+                        valid = false;
+                        continue;
+                    }
+                }
+            }
+
+            error!(loc, "undefined label `{value}`");
+            valid = false;
+
+            for (other, names) in &exported {
+                if names.contains(name.as_str()) {
+                    error!(
+                        "`{}` is exported by {other}",
+                        qualify(&namespaces[*other], &name)
+                    );
+                }
+            }
+        }
+
+        // exports only matter while loading
+        for statement in &mut self.program {
+            if let (Op::EXPORT(_), Some(_)) = (&statement.op, &statement.label) {
+                statement.op = Op::NOP;
+                statement.body.clear();
+            }
+        }
+        self.program
+            .retain(|statement| !matches!(statement.op, Op::EXPORT(_)));
+
+        match valid {
+            true => Ok(()),
+            false => Err(()),
+        }
+    }
+
+    /// registers, addresses and values the machine can't hold
+    fn validate_operands(&self) -> Result<()> {
+        let mut valid = true;
+
+        for statement in &self.program {
+            valid &= statement.validate(&self.config).is_ok();
+        }
+
+        match valid {
+            true => Ok(()),
+            false => Err(()),
+        }
+    }
+
     fn ensure_labels(&mut self) -> Result<()> {
         for statement in &self.program {
             match &statement.op {
@@ -99,7 +487,19 @@ impl DIS {
                     };
 
                     if !self.label_map.contains_key(target_label) {
-                        eprintln!("{loc}: undefined label `{target_label}`");
+                        error!(loc, "undefined label `{target_label}`");
+                        return Err(());
+                    }
+                }
+
+                Op::IVT(_) => {
+                    let (target_label, loc) = match &statement.body[1] {
+                        Token::Identifier { value, loc } => (value, loc),
+                        _ => unreachable!(),
+                    };
+
+                    if !self.label_map.contains_key(target_label) {
+                        error!(loc, "undefined label `{target_label}`");
                         return Err(());
                     }
                 }
@@ -111,20 +511,60 @@ impl DIS {
         Ok(())
     }
 
+    /// finds `<name>.dis` next to the including file, then in the include
+    /// paths and `DIS_PATH`, `std/<name>` is the bundled standard library
+    fn resolve_include(&self, name: &str, source_dir: &Path) -> Option<String> {
+        if let Some(std_name) = name.strip_prefix("std/") {
+            return stdlib::source(std_name).map(|_| stdlib::path(std_name));
+        }
+
+        let filename = format!("{}.dis", name);
+
+        let env_paths: Vec<PathBuf> = std::env::var_os("DIS_PATH")
+            .map(|paths| std::env::split_paths(&paths).collect())
+            .unwrap_or_default();
+
+        std::iter::once(source_dir.to_path_buf())
+            .chain(self.include_paths.iter().cloned())
+            .chain(env_paths)
+            .map(|dir| dir.join(&filename))
+            .find(|path| path.is_file())
+            .map(|path| path.to_str().unwrap().to_string())
+    }
+
+    /// lexes and parses `source_file` and its includes, each file is only
+    /// included once and `stack` holds the files currently being included
     fn lex_and_parse_file<T>(
+        &self,
         source_file: T,
-        include_map: &mut HashMap<String, HashSet<String>>,
-        parent: Option<String>,
+        graph: &mut IncludeGraph,
+        stack: &mut Vec<usize>,
     ) -> Result<Vec<Statement>>
     where
         T: Into<String>,
     {
         let source_file = source_file.into();
 
-        let mut lexer = Lexer::new(source_file.clone())?;
+        let bundled = source_file
+            .strip_prefix("<std>/")
+            .and_then(|name| name.strip_suffix(".dis"))
+            .and_then(stdlib::source);
+
+        let unsaved = std::fs::canonicalize(&source_file)
+            .ok()
+            .and_then(|path| self.unsaved.get(&path));
+
+        let mut lexer = match (bundled, unsaved) {
+            (Some(source), _) => Lexer::from_source(source_file.clone(), source.to_string()),
+            (None, Some(source)) => Lexer::from_source(source_file.clone(), source.clone()),
+            (None, None) => Lexer::new(source_file.clone())?,
+        };
         let mut tokens = lexer.tokens()?;
         let mut statements = Vec::new();
 
+        let id = graph.add_file(&source_file);
+        stack.push(id);
+
         while !tokens.is_empty() {
             let statement = Statement::parse(&mut tokens)?;
 
@@ -136,46 +576,42 @@ impl DIS {
                             _ => unreachable!(),
                         };
 
+                        let source_dir = Path::new(&source_file).parent().unwrap();
+
+                        let Some(include_filepath) = self.resolve_include(filename, source_dir)
+                        else {
+                            error!(token.loc(), "cannot find include `{filename}`");
+                            return Err(());
+                        };
+
                         let filename = format!("{}.dis", filename);
 
-                        let source_path = std::path::Path::new(&source_file);
-                        let source_dir = source_path.parent().unwrap();
-                        let source_path = source_path.to_str().unwrap().to_string();
-                        let include_filepath: String =
-                            source_dir.join(&filename).to_str().unwrap().to_string();
-
-                        if include_map.contains_key(&include_filepath) {
-                            let target_set = include_map.get(&include_filepath).unwrap();
-                            if target_set.contains(&source_path) {
-                                eprintln!(
-                                    "{loc}: circular include detected: `{filename}`",
-                                    loc = token.loc()
-                                );
+                        match graph.id(&include_filepath) {
+                            Some(include_id) if stack.contains(&include_id) => {
+                                error!(token.loc(), "circular include detected: `{filename}`");
+
+                                let start = stack.iter().position(|&i| i == include_id).unwrap();
+                                let chain: Vec<&str> = stack[start..]
+                                    .iter()
+                                    .chain([include_id].iter())
+                                    .map(|&i| graph.path(i))
+                                    .collect();
+                                error!("include chain: {}", chain.join(" -> "));
+
                                 return Err(());
                             }
-                        }
-
-                        if !include_map.contains_key(&source_path) {
-                            include_map.insert(source_path.clone(), HashSet::new());
-                        }
+                            // already included
+                            Some(include_id) => graph.add_include(id, include_id),
+                            None => {
+                                let inc_statements =
+                                    self.lex_and_parse_file(&include_filepath, graph, stack)?;
 
-                        // add import to set
-                        let include_set = include_map.get_mut(&source_path).unwrap();
-                        include_set.insert(include_filepath.clone());
+                                let include_id = graph.id(&include_filepath).unwrap();
+                                graph.add_include(id, include_id);
 
-                        // add import to parent set
-                        if let Some(parent_path) = &parent {
-                            let parent_set = include_map.get_mut(parent_path).unwrap();
-                            parent_set.insert(include_filepath.clone());
+                                statements.extend(inc_statements);
+                            }
                         }
-
-                        let inc_statements = DIS::lex_and_parse_file(
-                            include_filepath,
-                            include_map,
-                            Some(source_path),
-                        )?;
-
-                        statements.extend(inc_statements);
                     }
 
                     _ => {
@@ -185,6 +621,8 @@ impl DIS {
             }
         }
 
+        stack.pop();
+
         Ok(statements)
     }
 
@@ -197,7 +635,7 @@ impl DIS {
         }
     }
 
-    fn get_value(&self, src_token: &Token) -> Result<u16> {
+    fn get_value(&mut self, src_token: &Token) -> Result<Word> {
         match src_token {
             Token::Number { value, .. } => Ok(*value),
             Token::Register { value: reg_id, .. } => {
@@ -206,21 +644,22 @@ impl DIS {
             }
             Token::Memory { value: mem_id, .. } => {
                 let mem_addr = self.mem_addr_from_id(mem_id);
-                let value = self.memory.get(mem_addr).unwrap();
-                Ok(*value)
+                self.read_memory(mem_addr, src_token.loc())
             }
-            Token::Char { value, .. } => Ok(*value as u16),
+            Token::Char { value, .. } => Ok(*value as Word),
             other => {
-                eprintln!(
-                    "{loc}: expected number, register, memory or char, found `{src_token}`",
-                    loc = other.loc()
+                error!(
+                    other.loc(),
+                    "expected number, register, memory or char, found `{src_token}`"
                 );
                 Err(())
             }
         }
     }
 
-    fn set_value(&mut self, dst_token: &Token, value: u16) -> Result<()> {
+    fn set_value(&mut self, dst_token: &Token, value: Word) -> Result<()> {
+        let value = value & self.config.word_width.mask();
+
         match dst_token {
             Token::Register { value: reg_id, .. } => {
                 let reg = self.registers.get_mut(reg_id).unwrap();
@@ -229,66 +668,185 @@ impl DIS {
             }
             Token::Memory { value: mem_id, .. } => {
                 let mem_addr = self.mem_addr_from_id(mem_id);
-                let mem = self.memory.get_mut(mem_addr).unwrap();
-                *mem = value;
-                Ok(())
+                self.write_memory(mem_addr, value, dst_token.loc())
             }
             other => {
-                eprintln!(
-                    "{loc}: expected register or memory, found `{dst_token}`",
-                    loc = other.loc()
+                error!(
+                    other.loc(),
+                    "expected register or memory, found `{dst_token}`"
                 );
                 Err(())
             }
         }
     }
 
-    pub fn step(&mut self, out: &mut dyn Write, inp: &mut dyn BufRead) {
+    /// memory holds chars as Unicode scalar values, `None` for chars the
+    /// machine can't store
+    fn char_value(&self, c: char) -> Option<Word> {
+        let val = c as Word;
+
+        if self.config.ascii && !c.is_ascii() || val > self.config.word_width.mask() {
+            return None;
+        }
+
+        Some(val)
+    }
+
+    fn write_char(&self, out: &mut dyn Write, val: Word, loc: &Location) -> Result<()> {
+        let Some(c) = char::from_u32(val).filter(|c| !self.config.ascii || c.is_ascii()) else {
+            let encoding = match self.config.ascii {
+                true => "ASCII",
+                false => "Unicode",
+            };
+            error!(loc, "`{val}` is not a valid {encoding} char");
+            return Err(());
+        };
+
+        write!(out, "{c}").expect("write error");
+        Ok(())
+    }
+
+    /// `val` as printed by `prt`, `prx`, `prb` or `prs` with an optional
+    /// `w<N>` or `z<N>` width
+    fn format_number(&self, op: &Op, val: Word, format: Option<&Token>) -> String {
+        let s = match op {
+            Op::PRX(_) => format!("{:x}", val),
+            Op::PRB(_) => format!("{:b}", val),
+            Op::PRS(_) => format!("{}", self.config.word_width.signed(val)),
+            _ => format!("{}", val),
+        };
+
+        let (pad, width) = match format {
+            Some(Token::Identifier { value, .. }) => {
+                (value.starts_with('z'), value[1..].parse::<usize>().unwrap())
+            }
+            _ => return s,
+        };
+
+        match (pad, s.strip_prefix('-')) {
+            (true, Some(digits)) => {
+                format!("-{:0>width$}", digits, width = width.saturating_sub(1))
+            }
+            (true, None) => format!("{:0>width$}", s),
+            (false, _) => format!("{:>width$}", s),
+        }
+    }
+
+    fn mem_addr(&self, token: &Token) -> usize {
+        match token {
+            Token::Memory { value, .. } => self.mem_addr_from_id(value),
+            _ => unreachable!(),
+        }
+    }
+
+    /// null terminated string at `addr`, `None` when memory ends first
+    fn read_string(&mut self, addr: usize, loc: &Location) -> Option<Vec<Word>> {
+        let mut string = Vec::new();
+
+        for addr in addr..self.memory.len() {
+            match self.read_memory(addr, loc).ok()? {
+                0 => return Some(string),
+                c => string.push(c),
+            }
+        }
+
+        None
+    }
+
+    /// `#e` is set to 2 when a read finds no more input
+    fn end_of_input(&mut self) {
+        self.eof = true;
+        self.registers.insert("e".to_string(), 2);
+    }
+
+    pub fn step(&mut self, out: &mut dyn Write, inp: &mut dyn Input) {
+        // runtime errors are reported where they happen and end the program
+        if self.execute(out, inp).is_err() {
+            self.exit_code = 1;
+            self.die = true;
+        }
+
+        // devices print to the same `out` as the program
+        for (_, device) in &mut self.devices {
+            device.flush(out);
+        }
+    }
+
+    fn execute(&mut self, out: &mut dyn Write, inp: &mut dyn Input) -> Result<()> {
         if self.die {
-            return;
+            return Ok(());
+        }
+
+        self.interrupts.tick();
+
+        if self.interrupts.enabled
+            && self.interrupts.vectors.contains_key(&interrupt::INPUT)
+            && inp.ready()
+        {
+            self.interrupts.pending.insert(interrupt::INPUT);
+        }
+
+        // taking an interrupt uses up the step, the handler starts on the next
+        if let Some((interrupt, target)) = self.interrupts.take() {
+            self.interrupts.frames.push(Frame {
+                interrupt,
+                pc: self.pc,
+                cmp: self.cmp,
+            });
+            self.interrupts.enabled = false;
+            self.pc = target;
+            return Ok(());
         }
 
         let statement = self.program.get(self.pc);
 
         if statement.is_none() {
             self.die = true;
-            return;
+            return Ok(());
         }
 
         let statement = statement.unwrap().clone();
 
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(self.pc, &self.return_stack, &self.program, &self.label_map);
+        }
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_statement(&statement);
+        }
+
         match &statement.op {
             Op::MOV(_) => {
                 let src = &statement.body[0];
                 let dst = &statement.body[1];
 
-                let val = self.get_value(src).unwrap();
-                self.set_value(dst, val).unwrap();
+                let val = self.get_value(src)?;
+                self.set_value(dst, val)?;
             }
             Op::ADD(_) => {
                 let src = &statement.body[0];
                 let dst = &statement.body[1];
 
-                let val = self.get_value(src).unwrap();
-                let dst_val = self.get_value(dst).unwrap();
+                let val = self.get_value(src)?;
+                let dst_val = self.get_value(dst)?;
 
-                self.set_value(dst, val + dst_val).unwrap();
+                self.set_value(dst, dst_val.wrapping_add(val))?;
             }
             Op::SUB(_) => {
                 let src = &statement.body[0];
                 let dst = &statement.body[1];
 
-                let val = self.get_value(src).unwrap();
-                let dst_val = self.get_value(dst).unwrap();
+                let val = self.get_value(src)?;
+                let dst_val = self.get_value(dst)?;
 
-                self.set_value(dst, dst_val - val).unwrap();
+                self.set_value(dst, dst_val.wrapping_sub(val))?;
             }
             Op::CMP(_) => {
                 let src = &statement.body[0];
                 let dst = &statement.body[1];
 
-                let src_val = self.get_value(src).unwrap();
-                let dst_val = self.get_value(dst).unwrap();
+                let src_val = self.get_value(src)?;
+                let dst_val = self.get_value(dst)?;
 
                 self.cmp = 0;
 
@@ -314,9 +872,14 @@ impl DIS {
 
                 let target_idx = self.label_map.get(target_label).unwrap();
 
-                if self.cmp & CMP::LT as u8 != 0 {
+                let taken = self.cmp & CMP::LT as u8 != 0;
+                if taken {
                     self.pc = *target_idx - 1;
                 }
+
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.record_branch(&statement, taken);
+                }
             }
             Op::JGT(_) => {
                 let target_token = &statement.body[0];
@@ -328,9 +891,14 @@ impl DIS {
 
                 let target_idx = self.label_map.get(target_label).unwrap();
 
-                if self.cmp & CMP::GT as u8 != 0 {
+                let taken = self.cmp & CMP::GT as u8 != 0;
+                if taken {
                     self.pc = *target_idx - 1;
                 }
+
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.record_branch(&statement, taken);
+                }
             }
             Op::JEQ(_) => {
                 let target_token = &statement.body[0];
@@ -342,9 +910,14 @@ impl DIS {
 
                 let target_idx = self.label_map.get(target_label).unwrap();
 
-                if self.cmp & CMP::EQ as u8 != 0 {
+                let taken = self.cmp & CMP::EQ as u8 != 0;
+                if taken {
                     self.pc = *target_idx - 1;
                 }
+
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.record_branch(&statement, taken);
+                }
             }
 
             Op::JNE(_) => {
@@ -357,9 +930,14 @@ impl DIS {
 
                 let target_idx = self.label_map.get(target_label).unwrap();
 
-                if self.cmp & CMP::EQ as u8 == 0 {
+                let taken = self.cmp & CMP::EQ as u8 == 0;
+                if taken {
                     self.pc = *target_idx - 1;
                 }
+
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.record_branch(&statement, taken);
+                }
             }
 
             Op::JMP(_) => {
@@ -389,30 +967,40 @@ impl DIS {
                 self.pc = *target_idx - 1;
             }
 
-            Op::RET(_) => {
-                let return_idx = self.return_stack.pop().unwrap();
+            Op::RET(token) => {
+                let Some(return_idx) = self.return_stack.pop() else {
+                    error!(token.loc(), "`ret` without a `run` to return to");
+                    return Err(());
+                };
                 self.pc = return_idx;
             }
 
             Op::DIE(_) => {
+                if let Some(src) = statement.body.first() {
+                    self.exit_code = self.get_value(src)?;
+                }
                 self.die = true;
             }
 
             Op::OUT(_) => {
                 let src = &statement.body[0];
-                let val = self.get_value(src).unwrap();
-                write!(out, "{}", val as u8 as char).expect("write error");
+                let val = self.get_value(src)?;
+
+                if self.write_char(out, val, src.loc()).is_err() {
+                    return Err(());
+                }
             }
 
-            Op::PRT(_) => {
+            Op::PRT(_) | Op::PRX(_) | Op::PRB(_) | Op::PRS(_) => {
                 let src = &statement.body[0];
-                let val = self.get_value(src).unwrap();
-                write!(out, "{}", val).expect("write error");
+                let val = self.get_value(src)?;
+                let s = self.format_number(&statement.op, val, statement.body.get(1));
+                write!(out, "{}", s).expect("write error");
             }
 
             Op::DBG(_) => {
                 let src = &statement.body[0];
-                let val = self.get_value(src).unwrap();
+                let val = self.get_value(src)?;
                 match src {
                     Token::Memory { value, .. } => {
                         if value.starts_with("#") {
@@ -423,7 +1011,7 @@ impl DIS {
                         }
                     }
 
-                    _ => println!("DBG {src}: {val}"),
+                    _ => writeln!(out, "DBG {src}: {val}").expect("write error"),
                 }
             }
 
@@ -437,14 +1025,16 @@ impl DIS {
                 inp.read_until(b'\n', &mut buf).expect("read error");
 
                 let input = String::from_iter(buf.iter().map(|&c| c as char));
-                let val = input.trim().parse::<u16>();
+                let val = self.config.word_width.parse(input.trim());
 
-                if val.is_err() {
+                if buf.is_empty() {
+                    self.end_of_input();
+                } else if val.is_none() {
                     self.registers.insert("e".to_string(), 1);
                 } else {
                     self.registers.insert("e".to_string(), 0);
                     let val = val.unwrap();
-                    self.set_value(dst, val).unwrap();
+                    self.set_value(dst, val)?;
                 }
             }
 
@@ -453,35 +1043,56 @@ impl DIS {
                 let mut buf = Vec::new();
                 inp.read_until(b'\n', &mut buf).expect("read error");
 
-                let input = String::from_iter(buf.iter().map(|&c| c as char));
-                let val = input.trim().chars().nth(0);
+                let input = String::from_utf8_lossy(&buf);
+                let val = input.trim().chars().nth(0).and_then(|c| self.char_value(c));
 
-                if val.is_none() {
+                if buf.is_empty() {
+                    self.end_of_input();
+                } else if val.is_none() {
                     self.registers.insert("e".to_string(), 1);
                 } else {
                     self.registers.insert("e".to_string(), 0);
 
-                    let val = val.unwrap() as u16;
-                    self.set_value(dst, val).unwrap();
+                    let val = val.unwrap();
+                    self.set_value(dst, val)?;
                 }
             }
 
             Op::RLN(_) => {
                 let dst = &statement.body[0];
                 let max_c = &statement.body[1];
-                let mut max_c = self.get_value(max_c).unwrap();
+                let max_c = self.get_value(max_c)? as usize;
 
                 let mut buf = Vec::new();
                 inp.read_until(b'\n', &mut buf).expect("read error");
 
-                let input = String::from_iter(buf.iter().map(|&c| c as char));
-                let mut val = input.trim();
-                if max_c != 0 {
-                    max_c = max_c.min(val.len() as u16);
-                    val = &val[..max_c as usize];
+                let input = String::from_utf8_lossy(&buf);
+                let mut val = Vec::new();
+                let mut valid = true;
+                for c in input.trim().chars() {
+                    if max_c != 0 && val.len() == max_c {
+                        break;
+                    }
+
+                    // stop at the first char that can't be stored
+                    match self.char_value(c) {
+                        Some(c) => val.push(c),
+                        None => {
+                            valid = false;
+                            break;
+                        }
+                    }
                 }
 
-                self.registers.insert("3".to_string(), val.len() as u16);
+                self.registers.insert("3".to_string(), val.len() as Word);
+
+                if buf.is_empty() {
+                    self.end_of_input();
+                } else if !valid {
+                    self.registers.insert("e".to_string(), 1);
+                } else {
+                    self.registers.insert("e".to_string(), 0);
+                }
 
                 let mem_addr = {
                     let mem_id = match dst {
@@ -497,17 +1108,190 @@ impl DIS {
                     }
                 };
 
-                for (i, c) in val.chars().enumerate() {
-                    self.memory[mem_addr + i] = c as u16;
+                for (i, c) in val.into_iter().enumerate() {
+                    self.write_memory(mem_addr + i, c, dst.loc())?;
                 }
             }
 
-            Op::NOP => {
-                todo!()
+            Op::RDK(_) => {
+                let dst = &statement.body[0];
+
+                if inp.ready() {
+                    // a char that can't be stored is skipped like one that
+                    // hasn't arrived yet
+                    match read_char(inp).and_then(|c| self.char_value(c)) {
+                        Some(val) => {
+                            self.registers.insert("e".to_string(), 0);
+                            self.set_value(dst, val)?;
+                        }
+                        None => {
+                            self.registers.insert("e".to_string(), 1);
+                        }
+                    }
+                } else if inp.eof() {
+                    self.end_of_input();
+                } else {
+                    self.registers.insert("e".to_string(), 1);
+                }
+            }
+
+            Op::OTS(_) => {
+                let src = &statement.body[0];
+                let addr = self.mem_addr(src);
+
+                let Some(string) = self.read_string(addr, src.loc()) else {
+                    error!(
+                        src.loc(),
+                        "string at &{addr} is not terminated before the end of memory ({} words)",
+                        self.memory.len()
+                    );
+                    return Err(());
+                };
+
+                for c in string {
+                    if self.write_char(out, c, src.loc()).is_err() {
+                        return Err(());
+                    }
+                }
             }
+
+            Op::LEN(_) => {
+                let src = &statement.body[0];
+                let dst = &statement.body[1];
+                let addr = self.mem_addr(src);
+
+                let Some(string) = self.read_string(addr, src.loc()) else {
+                    error!(
+                        src.loc(),
+                        "string at &{addr} is not terminated before the end of memory ({} words)",
+                        self.memory.len()
+                    );
+                    return Err(());
+                };
+
+                self.set_value(dst, string.len() as Word)?;
+            }
+
+            Op::CPY(token) => {
+                let src = &statement.body[0];
+                let dst = &statement.body[1];
+                let count = &statement.body[2];
+
+                let src_addr = self.mem_addr(src);
+                let dst_addr = self.mem_addr(dst);
+                let count = self.get_value(count)? as usize;
+
+                let end = self.memory.len();
+                if src_addr + count > end || dst_addr + count > end {
+                    error!(
+                        token.loc(),
+                        "copying {count} words from &{src_addr} to &{dst_addr} goes past the end of memory ({end} words)"
+                    );
+                    return Err(());
+                }
+
+                // read everything first so overlapping ranges copy correctly
+                let words: Vec<Word> = (src_addr..src_addr + count)
+                    .map(|addr| self.read_memory(addr, src.loc()))
+                    .collect::<Result<_>>()?;
+
+                for (i, word) in words.into_iter().enumerate() {
+                    self.write_memory(dst_addr + i, word, dst.loc())?;
+                }
+            }
+
+            Op::CMPS(_) => {
+                let mut strings = Vec::new();
+
+                for src in &statement.body {
+                    let addr = self.mem_addr(src);
+
+                    let Some(string) = self.read_string(addr, src.loc()) else {
+                        error!(
+                            src.loc(),
+                            "string at &{addr} is not terminated before the end of memory ({} words)",
+                            self.memory.len()
+                        );
+                        return Err(());
+                    };
+
+                    strings.push(string);
+                }
+
+                self.cmp = match strings[0].cmp(&strings[1]) {
+                    Ordering::Equal => CMP::EQ as u8,
+                    Ordering::Less => CMP::LT as u8,
+                    Ordering::Greater => CMP::GT as u8,
+                };
+            }
+
+            Op::SYS(_) => {
+                let src = &statement.body[0];
+                let id = self.get_value(src)?;
+
+                let Some(mut handler) = self.syscalls.remove(&id) else {
+                    error!(src.loc(), "unknown syscall `{id}`");
+                    return Err(());
+                };
+
+                let result = handler(self);
+
+                // the handler may have registered a replacement for itself
+                self.syscalls.entry(id).or_insert(handler);
+
+                if result.is_err() {
+                    error!(src.loc(), "syscall `{id}` failed");
+                    return Err(());
+                }
+            }
+
+            Op::IVT(_) => {
+                let interrupt = match &statement.body[0] {
+                    Token::Number { value, .. } => *value,
+                    _ => unreachable!(),
+                };
+
+                let target_label = match &statement.body[1] {
+                    Token::Identifier { value, .. } => value,
+                    _ => unreachable!(),
+                };
+
+                let target_idx = self.label_map.get(target_label).unwrap();
+
+                self.interrupts.vectors.insert(interrupt, *target_idx);
+            }
+
+            Op::EI(_) => self.interrupts.enabled = true,
+
+            Op::DI(_) => self.interrupts.enabled = false,
+
+            Op::IRET(token) => {
+                let Some(frame) = self.interrupts.frames.pop() else {
+                    error!(token.loc(), "`iret` outside of an interrupt handler");
+                    return Err(());
+                };
+
+                // back to the statement the interrupt was taken before
+                self.pc = frame.pc;
+                self.cmp = frame.cmp;
+                self.interrupts.enabled = true;
+                return Ok(());
+            }
+
+            Op::TMR(_) => {
+                let src = &statement.body[0];
+                let steps = self.get_value(src)?;
+                self.interrupts.set_timer(steps);
+            }
+
+            // removed when the program is loaded
+            Op::EXPORT(_) => {}
+
+            Op::NOP => {}
         }
 
         self.pc += 1;
+        Ok(())
     }
 
     pub fn load<T>(&mut self, source_path: T) -> Result<()>
@@ -516,23 +1300,47 @@ impl DIS {
     {
         self.reset();
 
-        let mut include_map = HashMap::new();
-
-        let statements = DIS::lex_and_parse_file(source_path, &mut include_map, None)?;
+        let mut graph = IncludeGraph::new();
+        let statements = self.lex_and_parse_file(source_path, &mut graph, &mut Vec::new())?;
+        self.includes = graph;
 
         self.program = statements;
 
+        self.validate_operands()?;
+        self.resolve_labels()?;
         self.index_labels()?;
         self.ensure_labels()?;
 
         Ok(())
     }
 
-    pub fn run(&mut self) {
+    /// every file the loaded program was read from, the program file first
+    pub fn sources(&self) -> &[String] {
+        self.includes.files()
+    }
+
+    /// runs until `die` or the end of the program, returns the exit code
+    pub fn run(&mut self) -> Word {
         let out = &mut std::io::stdout();
-        let mut inp = BufReader::new(std::io::stdin());
+        let mut inp = StdinInput::new();
         while !self.die {
             self.step(out, &mut inp);
         }
+
+        self.exit_code
+    }
+
+    /// like `run` but stops after `max_steps` even if the program has not died
+    pub fn run_for(&mut self, max_steps: usize) -> Word {
+        let out = &mut std::io::stdout();
+        let mut inp = StdinInput::new();
+        for _ in 0..max_steps {
+            if self.die {
+                break;
+            }
+            self.step(out, &mut inp);
+        }
+
+        self.exit_code
     }
 }
@@ -0,0 +1,493 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+
+use crate::cfg::{self, successors};
+use crate::lexer::{Lexer, Location, Token};
+use crate::statement::{Op, Statement};
+use crate::{stdlib, DIS};
+
+/// every check `lint` runs, by the name suppression comments use
+pub const RULES: [&str; 5] = [
+    "unreachable",
+    "unused-label",
+    "ret-without-run",
+    "rln-overlap",
+    "uninitialized-register",
+];
+
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub rule: &'static str,
+    pub loc: Location,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: warning: {} [{}]", self.loc, self.message, self.rule)
+    }
+}
+
+/// Checks a loaded program for likely mistakes.
+///
+/// Warnings in the bundled standard library are left out, and so are the
+/// ones on a line ending in `- lint: allow [rules..]` or below a line that
+/// only holds that comment. Without rules every warning is allowed.
+///
+/// A program that exports labels is a library, it is not run from its first
+/// statement so nothing is checked along the way from there.
+pub fn lint(dis: &DIS) -> Vec<Warning> {
+    let program = &dis.program;
+    let labels = cfg::labels(program);
+
+    let mut warnings = Vec::new();
+    unreachable(program, &mut warnings);
+    unused_labels(dis, &mut warnings);
+    rln_overlap(program, &mut warnings);
+    if !is_library(dis) {
+        ret_without_run(program, &labels, &mut warnings);
+        uninitialized_registers(program, &labels, &mut warnings);
+    }
+
+    let mut allowed: HashMap<String, HashMap<usize, Vec<String>>> = HashMap::new();
+    warnings.retain(|warning| {
+        let file = warning.loc.file();
+        if file.starts_with("<std>/") {
+            return false;
+        }
+
+        let rules = allowed
+            .entry(file.to_string())
+            .or_insert_with(|| suppressions(dis, file))
+            .get(&warning.loc.line());
+
+        !rules.is_some_and(|rules| rules.is_empty() || rules.iter().any(|r| r == warning.rule))
+    });
+
+    warnings.sort_by(|a, b| a.loc.cmp(&b.loc));
+    warnings
+}
+
+/// whether the program's own file exports labels
+pub fn is_library(dis: &DIS) -> bool {
+    let Some(file) = dis.includes.files().first() else {
+        return false;
+    };
+
+    let tokens = source(dis, file)
+        .and_then(|source| Lexer::from_source(file.to_string(), source).tokens().ok());
+
+    tokens.is_some_and(|tokens| {
+        tokens
+            .iter()
+            .any(|token| matches!(token, Token::Keyword { value, .. } if value == "export"))
+    })
+}
+
+/// source of `file` as the program was loaded from it
+fn source(dis: &DIS, file: &str) -> Option<String> {
+    let unsaved = fs::canonicalize(file)
+        .ok()
+        .and_then(|path| dis.unsaved.get(&path));
+
+    match (file.strip_prefix("<std>/"), unsaved) {
+        (Some(name), _) => stdlib::source(name.trim_end_matches(".dis")).map(str::to_string),
+        (None, Some(source)) => Some(source.clone()),
+        (None, None) => fs::read_to_string(file).ok(),
+    }
+}
+
+/// the word at `loc` as it is written, labels are renamed while loading
+fn written(dis: &DIS, loc: &Location) -> Option<String> {
+    let source = source(dis, loc.file())?;
+    let line = source.lines().nth(loc.line().checked_sub(1)?)?;
+
+    let word: String = line
+        .chars()
+        .skip(loc.column().checked_sub(1)?)
+        .take_while(|c| !c.is_whitespace())
+        .collect();
+
+    Some(word.trim_end_matches(':').to_string())
+}
+
+/// rules allowed on each line of `file`
+fn suppressions(dis: &DIS, file: &str) -> HashMap<usize, Vec<String>> {
+    let mut allowed = HashMap::new();
+    let Some(source) = source(dis, file) else {
+        return allowed;
+    };
+
+    let Ok(tokens) = Lexer::from_source(file.to_string(), source)
+        .comments(true)
+        .tokens()
+    else {
+        return allowed;
+    };
+
+    let mut code_line = 0;
+    for token in tokens {
+        let Token::Comment { value, loc } = token else {
+            code_line = token.loc().line();
+            continue;
+        };
+
+        let Some(rules) = value[1..].trim().strip_prefix("lint: allow") else {
+            continue;
+        };
+
+        let rules: Vec<String> = rules
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|rule| !rule.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        // a comment on its own line is for the line below
+        let line = match code_line == loc.line() {
+            true => loc.line(),
+            false => loc.line() + 1,
+        };
+        allowed.insert(line, rules);
+    }
+
+    allowed
+}
+
+/// where a warning about `statement` points
+fn location(statement: &Statement) -> &Location {
+    match (statement.op.token(), &statement.label) {
+        (Some(token), _) => token.loc(),
+        (None, Some(label)) => label.loc(),
+        (None, None) => unreachable!(),
+    }
+}
+
+/// interrupt handlers set with `ivt`
+fn handlers(program: &[Statement], labels: &HashMap<&str, usize>) -> Vec<usize> {
+    program
+        .iter()
+        .filter(|statement| matches!(statement.op, Op::IVT(_)))
+        .filter_map(|statement| labels.get(statement.target()?).copied())
+        .collect()
+}
+
+/// statements after `jmp`, `die`, `ret` or `iret` that no label leads to
+fn unreachable(program: &[Statement], warnings: &mut Vec<Warning>) {
+    for pair in program.windows(2) {
+        let (prev, statement) = (&pair[0], &pair[1]);
+
+        // an included file starts where the `@` was, not after `prev`
+        if statement.label.is_some() || statement.file() != prev.file() {
+            continue;
+        }
+
+        if let Op::JMP(_) | Op::DIE(_) | Op::RET(_) | Op::IRET(_) = prev.op {
+            warnings.push(Warning {
+                rule: "unreachable",
+                loc: location(statement).clone(),
+                message: format!("unreachable code after `{}`", prev.op.mnemonic()),
+            });
+        }
+    }
+}
+
+/// labels nothing jumps to, runs or sets as a handler
+fn unused_labels(dis: &DIS, warnings: &mut Vec<Warning>) {
+    let used: HashSet<&str> = dis.program.iter().filter_map(Statement::target).collect();
+
+    for statement in &dis.program {
+        let Some(Token::Label { value, loc }) = &statement.label else {
+            continue;
+        };
+
+        if !used.contains(value.as_str()) && !dis.exports.contains(value) {
+            let name = written(dis, loc).unwrap_or_else(|| value.clone());
+            warnings.push(Warning {
+                rule: "unused-label",
+                loc: loc.clone(),
+                message: format!("label `{name}` is never used"),
+            });
+        }
+    }
+}
+
+/// `ret` the program or a handler gets to without going through `run`
+fn ret_without_run(
+    program: &[Statement],
+    labels: &HashMap<&str, usize>,
+    warnings: &mut Vec<Warning>,
+) {
+    if program.is_empty() {
+        return;
+    }
+
+    let mut stack = vec![0];
+    stack.extend(handlers(program, labels));
+    let mut seen = HashSet::new();
+
+    while let Some(idx) = stack.pop() {
+        if !seen.insert(idx) {
+            continue;
+        }
+
+        if let Op::RET(token) = &program[idx].op {
+            warnings.push(Warning {
+                rule: "ret-without-run",
+                loc: token.loc().clone(),
+                message: "`ret` can be reached without a `run`".to_string(),
+            });
+        }
+
+        stack.extend(successors(program, labels, idx, false));
+    }
+}
+
+/// operand a statement writes to
+fn destination(statement: &Statement) -> Option<&Token> {
+    match statement.op {
+        Op::MOV(_) | Op::ADD(_) | Op::SUB(_) | Op::LEN(_) | Op::CPY(_) => statement.body.get(1),
+        Op::RDN(_) | Op::RDC(_) | Op::RDK(_) | Op::RLN(_) => statement.body.first(),
+        _ => None,
+    }
+}
+
+fn constant_address(token: &Token) -> Option<usize> {
+    match token {
+        Token::Memory { value, .. } => value.parse().ok(),
+        _ => None,
+    }
+}
+
+fn constant(token: &Token) -> Option<usize> {
+    match token {
+        Token::Number { value, .. } => Some(*value as usize),
+        _ => None,
+    }
+}
+
+/// `rln` into a fixed address that other statements also write to, a line
+/// read without a limit can reach anything after its address
+fn rln_overlap(program: &[Statement], warnings: &mut Vec<Warning>) {
+    // fixed addresses written and the range each statement covers
+    let mut written: Vec<(usize, usize, usize)> = Vec::new();
+    for (idx, statement) in program.iter().enumerate() {
+        let Some(start) = destination(statement).and_then(constant_address) else {
+            continue;
+        };
+
+        let len = match statement.op {
+            Op::CPY(_) | Op::RLN(_) => match constant(&statement.body[statement.body.len() - 1]) {
+                Some(0) if matches!(statement.op, Op::RLN(_)) => usize::MAX - start,
+                Some(len) => len,
+                None => continue,
+            },
+            _ => 1,
+        };
+
+        if len > 0 {
+            written.push((idx, start, start.saturating_add(len)));
+        }
+    }
+
+    for &(idx, start, end) in &written {
+        if !matches!(program[idx].op, Op::RLN(_)) {
+            continue;
+        }
+
+        let overlap = written
+            .iter()
+            .filter(|(other, other_start, _)| {
+                *other != idx && start <= *other_start && *other_start < end
+            })
+            .min_by_key(|(_, other_start, _)| *other_start);
+
+        if let Some(&(other, addr, _)) = overlap {
+            warnings.push(Warning {
+                rule: "rln-overlap",
+                loc: location(&program[idx]).clone(),
+                message: format!(
+                    "`rln` can write over &{addr}, also written at {}",
+                    location(&program[other])
+                ),
+            });
+        }
+    }
+}
+
+fn register(token: &Token) -> Option<&str> {
+    match token {
+        Token::Register { value, .. } => Some(value),
+        Token::Memory { value, .. } => value.strip_prefix('#'),
+        _ => None,
+    }
+}
+
+/// registers whose value a statement uses, addresses included
+fn reads(statement: &Statement) -> Vec<&str> {
+    let overwritten = match statement.op {
+        Op::MOV(_) | Op::LEN(_) | Op::RDN(_) | Op::RDC(_) | Op::RDK(_) => {
+            destination(statement).filter(|token| matches!(token, Token::Register { .. }))
+        }
+        _ => None,
+    };
+
+    statement
+        .body
+        .iter()
+        .filter(|token| !overwritten.is_some_and(|dst| std::ptr::eq(dst, *token)))
+        .filter_map(register)
+        .collect()
+}
+
+fn writes(statement: &Statement) -> Vec<&str> {
+    let mut registers = Vec::new();
+
+    if let Some(Token::Register { value, .. }) = destination(statement) {
+        registers.push(value.as_str());
+    }
+
+    match statement.op {
+        Op::RDN(_) | Op::RDC(_) | Op::RDK(_) => registers.push("e"),
+        Op::RLN(_) => registers.extend(["3", "e"]),
+        // results of the default syscalls, other handlers can't be known
+        Op::SYS(_) => registers.extend(["0", "1"]),
+        _ => {}
+    }
+
+    registers
+}
+
+/// registers a routine may write before it returns, routines it runs
+/// included
+fn routine_writes<'a>(
+    program: &'a [Statement],
+    labels: &HashMap<&str, usize>,
+    entry: usize,
+) -> HashSet<&'a str> {
+    let mut registers = HashSet::new();
+    let mut stack = vec![entry];
+    let mut seen = HashSet::new();
+
+    while let Some(idx) = stack.pop() {
+        if !seen.insert(idx) {
+            continue;
+        }
+
+        registers.extend(writes(&program[idx]));
+        stack.extend(successors(program, labels, idx, true));
+
+        // the routine may come back to after a nested `run`
+        if let Op::RUN(_) = program[idx].op {
+            stack.extend((idx + 1 < program.len()).then_some(idx + 1));
+        }
+    }
+
+    registers
+}
+
+/// Registers read on some path before anything writes them.
+///
+/// A register counts as written at a statement when every path there writes
+/// it, a `run` writes whatever the routine may write. Handlers start with
+/// the registers written wherever interrupts get enabled.
+fn uninitialized_registers(
+    program: &[Statement],
+    labels: &HashMap<&str, usize>,
+    warnings: &mut Vec<Warning>,
+) {
+    if program.is_empty() {
+        return;
+    }
+
+    let mut routines: HashMap<usize, HashSet<&str>> = HashMap::new();
+    let mut written: Vec<Option<HashSet<&str>>> = vec![None; program.len()];
+    written[0] = Some(HashSet::new());
+    let mut stack = vec![0];
+
+    let handlers = handlers(program, labels);
+
+    loop {
+        while let Some(idx) = stack.pop() {
+            let statement = &program[idx];
+            let mut after = written[idx].clone().unwrap();
+            after.extend(writes(statement));
+
+            let mut flows: Vec<(usize, HashSet<&str>)> = successors(program, labels, idx, true)
+                .into_iter()
+                .map(|next| (next, after.clone()))
+                .collect();
+
+            if let (Op::RUN(_), Some(&target)) = (
+                &statement.op,
+                statement.target().and_then(|l| labels.get(l)),
+            ) {
+                if idx + 1 < program.len() {
+                    let routine = routines
+                        .entry(target)
+                        .or_insert_with(|| routine_writes(program, labels, target));
+                    flows.push((idx + 1, after.union(routine).copied().collect()));
+                }
+            }
+
+            for (next, registers) in flows {
+                if meet(&mut written[next], registers) {
+                    stack.push(next);
+                }
+            }
+        }
+
+        let enabled = program
+            .iter()
+            .zip(&written)
+            .filter(|(statement, _)| matches!(statement.op, Op::EI(_)))
+            .filter_map(|(_, registers)| registers.clone())
+            .reduce(|a, b| a.intersection(&b).copied().collect());
+
+        let Some(enabled) = enabled else {
+            break;
+        };
+
+        for &handler in &handlers {
+            if meet(&mut written[handler], enabled.clone()) {
+                stack.push(handler);
+            }
+        }
+
+        if stack.is_empty() {
+            break;
+        }
+    }
+
+    for (statement, registers) in program.iter().zip(&written) {
+        let Some(registers) = registers else {
+            continue;
+        };
+
+        let mut reported = HashSet::new();
+        for register in reads(statement) {
+            if !registers.contains(register) && reported.insert(register) {
+                warnings.push(Warning {
+                    rule: "uninitialized-register",
+                    loc: location(statement).clone(),
+                    message: format!("register `#{register}` is read before it is written"),
+                });
+            }
+        }
+    }
+}
+
+/// keeps the registers written on both paths, true when that changed them
+fn meet<'a>(written: &mut Option<HashSet<&'a str>>, registers: HashSet<&'a str>) -> bool {
+    match written {
+        None => {
+            *written = Some(registers);
+            true
+        }
+        Some(current) => {
+            let before = current.len();
+            current.retain(|register| registers.contains(register));
+            current.len() != before
+        }
+    }
+}
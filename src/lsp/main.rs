@@ -0,0 +1,576 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use serde_json::{json, Value};
+
+use dis::config::MachineConfig;
+use dis::diagnostic::{self, Diagnostic};
+use dis::docs;
+use dis::lexer::{Lexer, Location, Token, KEYWORDS};
+use dis::lint;
+use dis::protocol::{self, read_message, PARSE_ERROR};
+use dis::DIS;
+
+/// LSP error codes
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+fn send(out: &mut impl Write, message: Value) {
+    protocol::write_message(out, &message);
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+
+    let mut bytes = Vec::new();
+    let mut chars = path.bytes();
+    while let Some(b) = chars.next() {
+        match b {
+            b'%' => {
+                let hex = [chars.next()?, chars.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            _ => bytes.push(b),
+        }
+    }
+
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for b in path.to_string_lossy().bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{b:02X}")),
+        }
+    }
+    uri
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// word starting at a 1-based line and column, in chars
+fn word_len(source: &str, line: usize, column: usize) -> usize {
+    source
+        .lines()
+        .nth(line - 1)
+        .map(|text| {
+            text.chars()
+                .skip(column - 1)
+                .take_while(|c| !c.is_whitespace())
+                .count()
+        })
+        .unwrap_or(0)
+        .max(1)
+}
+
+/// LSP positions are 0-based, columns are counted in chars
+fn range(line: usize, column: usize, len: usize) -> Value {
+    json!({
+        "start": { "line": line - 1, "character": column - 1 },
+        "end": { "line": line - 1, "character": column - 1 + len },
+    })
+}
+
+/// range of the word at `loc`
+fn source_range(source: &str, loc: &Location) -> Value {
+    range(
+        loc.line(),
+        loc.column(),
+        word_len(source, loc.line(), loc.column()),
+    )
+}
+
+/// a label definition or a reference to it, by its program wide name
+struct Symbol {
+    name: String,
+    loc: Location,
+    definition: bool,
+}
+
+#[derive(Default)]
+struct Server {
+    /// open documents by canonical path
+    documents: HashMap<PathBuf, String>,
+    shutdown: bool,
+}
+
+impl Server {
+    fn source(&self, path: &Path) -> Option<String> {
+        match self.documents.get(&canonical(path)) {
+            Some(source) => Some(source.clone()),
+            None => fs::read_to_string(path).ok(),
+        }
+    }
+
+    /// loads `path` as a program, open documents are read instead of disk
+    fn load(&self, path: &Path) -> (Option<DIS>, Vec<Diagnostic>) {
+        let mut dis = DIS::new();
+        dis.unsaved = self.documents.clone();
+
+        let (result, diagnostics) =
+            diagnostic::capture(|| dis.load(path.to_string_lossy().to_string()));
+
+        (result.ok().map(|_| dis), diagnostics)
+    }
+
+    fn document_path(params: &Value) -> Option<PathBuf> {
+        uri_to_path(params["textDocument"]["uri"].as_str()?).map(|path| canonical(&path))
+    }
+
+    fn tokens(&self, path: &Path) -> Vec<Token> {
+        let source = self.source(path).unwrap_or_default();
+        Lexer::from_source(path.to_string_lossy().to_string(), source)
+            .tokens()
+            .unwrap_or_default()
+    }
+
+    /// token of the document under an LSP position
+    fn token_at(&self, path: &Path, params: &Value) -> Option<(Vec<Token>, usize)> {
+        let line = params["position"]["line"].as_u64()? as usize + 1;
+        let column = params["position"]["character"].as_u64()? as usize + 1;
+        let source = self.source(path)?;
+
+        let tokens = self.tokens(path);
+        let idx = tokens.iter().position(|token| {
+            let loc = token.loc();
+            let len = word_len(&source, loc.line(), loc.column());
+            loc.line() == line && loc.column() <= column && column < loc.column() + len
+        })?;
+
+        Some((tokens, idx))
+    }
+
+    fn lsp_location(&self, loc: &Location) -> Option<Value> {
+        // the bundled library has no file to open
+        if loc.file().starts_with("<std>/") {
+            return None;
+        }
+
+        let path = canonical(Path::new(loc.file()));
+        Some(json!({
+            "uri": path_to_uri(&path),
+            "range": source_range(&self.source(&path)?, loc),
+        }))
+    }
+
+    fn publish_diagnostics(&self, out: &mut impl Write, path: &Path) {
+        let (dis, diagnostics) = self.load(path);
+        let source = self.source(path).unwrap_or_default();
+        let mut items: Vec<Value> = Vec::new();
+
+        for diagnostic in diagnostics {
+            match &diagnostic.loc {
+                Some(loc) if canonical(Path::new(loc.file())) == path => items.push(json!({
+                    "range": source_range(&source, loc),
+                    "severity": 1,
+                    "source": "dis",
+                    "message": diagnostic.message,
+                })),
+                // problems in included files show at the top
+                Some(loc) => items.push(json!({
+                    "range": range(1, 1, 1),
+                    "severity": 1,
+                    "source": "dis",
+                    "message": format!("{loc}: {}", diagnostic.message),
+                })),
+                // notes like the include chain belong to the error before
+                None => match items.last_mut() {
+                    Some(item) => {
+                        let message = format!(
+                            "{}\n{}",
+                            item["message"].as_str().unwrap_or_default(),
+                            diagnostic.message
+                        );
+                        item["message"] = json!(message);
+                    }
+                    None => items.push(json!({
+                        "range": range(1, 1, 1),
+                        "severity": 1,
+                        "source": "dis",
+                        "message": diagnostic.message,
+                    })),
+                },
+            }
+        }
+
+        if let Some(dis) = dis {
+            for warning in lint::lint(&dis) {
+                let loc = &warning.loc;
+                if canonical(Path::new(loc.file())) != path {
+                    continue;
+                }
+
+                items.push(json!({
+                    "range": source_range(&source, loc),
+                    "severity": 2,
+                    "source": "dis lint",
+                    "code": warning.rule,
+                    "message": warning.message,
+                }));
+            }
+        }
+
+        send(
+            out,
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": path_to_uri(path), "diagnostics": items },
+            }),
+        );
+    }
+
+    /// includes can change with any document, so every one is checked again
+    fn publish_all(&self, out: &mut impl Write) {
+        for path in self.documents.keys() {
+            self.publish_diagnostics(out, path);
+        }
+    }
+
+    /// labels of the program `path` is the main file of
+    fn symbols(&self, path: &Path) -> Vec<Symbol> {
+        let (Some(dis), _) = self.load(path) else {
+            return Vec::new();
+        };
+
+        let mut symbols = Vec::new();
+        for statement in &dis.program {
+            if let Some(Token::Label { value, loc }) = &statement.label {
+                symbols.push(Symbol {
+                    name: value.clone(),
+                    loc: loc.clone(),
+                    definition: true,
+                });
+            }
+
+            if let Some(operand) = statement.target_operand() {
+                if let Token::Identifier { value, loc } = &statement.body[operand] {
+                    symbols.push(Symbol {
+                        name: value.clone(),
+                        loc: loc.clone(),
+                        definition: false,
+                    });
+                }
+            }
+        }
+
+        symbols
+    }
+
+    /// program wide name of the label under the cursor
+    fn symbol_at(&self, path: &Path, params: &Value) -> Option<(Vec<Symbol>, String)> {
+        let (tokens, idx) = self.token_at(path, params)?;
+        let loc = tokens[idx].loc();
+
+        let symbols = self.symbols(path);
+        let name = symbols
+            .iter()
+            .find(|symbol| {
+                symbol.loc.line() == loc.line()
+                    && symbol.loc.column() == loc.column()
+                    && canonical(Path::new(symbol.loc.file())) == path
+            })?
+            .name
+            .clone();
+
+        Some((symbols, name))
+    }
+
+    fn definition(&self, path: &Path, params: &Value) -> Value {
+        let Some((symbols, name)) = self.symbol_at(path, params) else {
+            return Value::Null;
+        };
+
+        symbols
+            .iter()
+            .find(|symbol| symbol.definition && symbol.name == name)
+            .and_then(|symbol| self.lsp_location(&symbol.loc))
+            .unwrap_or(Value::Null)
+    }
+
+    fn references(&self, path: &Path, params: &Value) -> Value {
+        let Some((symbols, name)) = self.symbol_at(path, params) else {
+            return Value::Null;
+        };
+
+        let declaration = params["context"]["includeDeclaration"]
+            .as_bool()
+            .unwrap_or(true);
+
+        let locations: Vec<Value> = symbols
+            .iter()
+            .filter(|symbol| symbol.name == name && (declaration || !symbol.definition))
+            .filter_map(|symbol| self.lsp_location(&symbol.loc))
+            .collect();
+
+        json!(locations)
+    }
+
+    fn hover(&self, path: &Path, params: &Value) -> Value {
+        let Some((tokens, idx)) = self.token_at(path, params) else {
+            return Value::Null;
+        };
+
+        // instruction the operand belongs to
+        let instruction = tokens[..idx].iter().rev().find_map(|token| match token {
+            Token::Keyword { value, .. } => docs::instruction(value),
+            _ => None,
+        });
+
+        let operand = match &tokens[idx] {
+            Token::Keyword { value, .. } => {
+                let Some((syntax, summary)) = docs::instruction(value) else {
+                    return Value::Null;
+                };
+                let value = format!("```\n{syntax}\n```\n{summary}");
+                return json!({ "contents": { "kind": "markdown", "value": value } });
+            }
+            Token::Label { value, .. } => format!("`LBL` label `{value}`"),
+            Token::Register { value, .. } if value == "e" => {
+                "`REG` register `#e`, set by reads to report errors".to_string()
+            }
+            Token::Register { value, .. } => format!("`REG` register `#{value}`"),
+            Token::Memory { value, .. } => match value.strip_prefix('#') {
+                Some(reg) => format!("`MEM` memory at the address in register `#{reg}`"),
+                None => format!("`MEM` memory at address {value}"),
+            },
+            Token::Number { value, .. } => format!("`NUM` number {value} (0x{value:x})"),
+            Token::Char { value, .. } => format!("`CHR` char `{value}` ({})", *value as u32),
+            Token::Identifier { value, .. } => match self.symbol_at(path, params) {
+                Some((symbols, name)) => {
+                    let definition = symbols
+                        .iter()
+                        .find(|symbol| symbol.definition && symbol.name == name);
+                    match definition {
+                        Some(symbol) => format!("`LBL` label `{name}`, defined at {}", symbol.loc),
+                        None => format!("`LBL` label `{name}`"),
+                    }
+                }
+                None => format!("`{value}`"),
+            },
+            Token::Comment { .. } => return Value::Null,
+        };
+
+        let value = match instruction {
+            Some((syntax, _)) => format!("{operand}\n\n```\n{syntax}\n```"),
+            None => operand,
+        };
+
+        json!({ "contents": { "kind": "markdown", "value": value } })
+    }
+
+    fn completion(&self, path: &Path) -> Value {
+        let mut items = Vec::new();
+
+        for keyword in KEYWORDS {
+            let (detail, documentation) = docs::instruction(keyword).unwrap_or_default();
+            items.push(json!({
+                "label": keyword,
+                "kind": 14,
+                "detail": detail,
+                "documentation": documentation,
+            }));
+        }
+
+        for register in MachineConfig::default().register_names() {
+            items.push(json!({ "label": format!("#{register}"), "kind": 6 }));
+        }
+
+        let mut labels: Vec<String> = self
+            .tokens(path)
+            .iter()
+            .filter_map(|token| match token {
+                Token::Label { value, .. } => Some(value.clone()),
+                _ => None,
+            })
+            .collect();
+
+        // labels other files export, by the name this file uses
+        if let (Some(dis), _) = self.load(path) {
+            labels.extend(
+                dis.exports
+                    .iter()
+                    .filter(|name| name.contains('.'))
+                    .cloned(),
+            );
+        }
+
+        labels.sort();
+        labels.dedup();
+        for label in labels {
+            items.push(json!({ "label": label, "kind": 18 }));
+        }
+
+        json!(items)
+    }
+
+    /// labels, with `.local` labels inside the label they belong to
+    fn document_symbols(&self, path: &Path) -> Value {
+        let source = self.source(path).unwrap_or_default();
+        let mut symbols: Vec<Value> = Vec::new();
+
+        for token in self.tokens(path) {
+            let Token::Label { value, loc } = token else {
+                continue;
+            };
+
+            let range = range(
+                loc.line(),
+                loc.column(),
+                word_len(&source, loc.line(), loc.column()),
+            );
+            let symbol = json!({
+                "name": value,
+                "kind": 12,
+                "range": range,
+                "selectionRange": range,
+                "children": [],
+            });
+
+            match (value.starts_with('.'), symbols.last_mut()) {
+                (true, Some(global)) => global["children"].as_array_mut().unwrap().push(symbol),
+                _ => symbols.push(symbol),
+            }
+        }
+
+        json!(symbols)
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        if method == "initialize" {
+            return Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": { "triggerCharacters": ["#", "."] },
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "dis-lsp", "version": env!("CARGO_PKG_VERSION") },
+            }));
+        }
+
+        if method == "shutdown" {
+            self.shutdown = true;
+            return Ok(Value::Null);
+        }
+
+        let Some(path) = Server::document_path(params) else {
+            return match method.starts_with("textDocument/") {
+                true => Err((INVALID_PARAMS, "expected a file:// document".to_string())),
+                false => Err((METHOD_NOT_FOUND, format!("unknown method `{method}`"))),
+            };
+        };
+
+        match method {
+            "textDocument/definition" => Ok(self.definition(&path, params)),
+            "textDocument/references" => Ok(self.references(&path, params)),
+            "textDocument/hover" => Ok(self.hover(&path, params)),
+            "textDocument/completion" => Ok(self.completion(&path)),
+            "textDocument/documentSymbol" => Ok(self.document_symbols(&path)),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{method}`"))),
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: &Value, out: &mut impl Write) {
+        let Some(path) = Server::document_path(params) else {
+            return;
+        };
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(path, text.to_string());
+                self.publish_all(out);
+            }
+            "textDocument/didChange" => {
+                // full sync, the last change has the whole document
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes.and_then(|c| c.last()?["text"].as_str()) {
+                    self.documents.insert(path, text.to_string());
+                }
+                self.publish_all(out);
+            }
+            "textDocument/didSave" => self.publish_all(out),
+            "textDocument/didClose" => {
+                self.documents.remove(&path);
+                send(
+                    out,
+                    json!({
+                        "jsonrpc": "2.0",
+                        "method": "textDocument/publishDiagnostics",
+                        "params": { "uri": path_to_uri(&path), "diagnostics": [] },
+                    }),
+                );
+                self.publish_all(out);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() > 1 {
+        println!("Usage: {}", args[0]);
+        println!("Language server for DIS, speaks LSP over stdin and stdout");
+        return ExitCode::FAILURE;
+    }
+
+    let mut input = io::stdin().lock();
+    let mut out = io::stdout().lock();
+    let mut server = Server::default();
+
+    while let Some(message) = read_message(&mut input) {
+        let message = match message {
+            Ok(message) => message,
+            Err(malformed) => {
+                eprintln!("dis-lsp: malformed message: {}", malformed.error);
+                if let Some(id) = malformed.field("id") {
+                    let error = json!({ "code": PARSE_ERROR, "message": malformed.error });
+                    send(
+                        &mut out,
+                        json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+                    );
+                }
+                continue;
+            }
+        };
+
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+
+        match (method, message.get("id")) {
+            ("exit", _) => break,
+            // responses to requests we never make
+            ("", _) => {}
+            (_, Some(id)) => {
+                let response = match server.request(method, params) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, message)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": message },
+                    }),
+                };
+                send(&mut out, response);
+            }
+            (_, None) => server.notify(method, params, &mut out),
+        }
+    }
+
+    match server.shutdown {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
@@ -1,27 +1,626 @@
-use std::{collections::VecDeque, process::ExitCode};
+use std::io::{BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::File,
+    process::ExitCode,
+};
 
+use dis::cfg::Cfg;
+use dis::config::{parse_address, MachineConfig, WordWidth};
+use dis::coverage::Coverage;
+use dis::device::{Console, Device, Keyboard, Random, Timer};
+use dis::formatter;
+use dis::framebuffer::{Framebuffer, Palette};
+use dis::harness::{self, Outcome, TestCase};
+use dis::lint;
+use dis::profile::Profiler;
+use dis::repl::Repl;
 use dis::DIS;
 
+fn usage(program: &str) {
+    println!("Usage: {} [run] [options] <program.dis>", program);
+    println!("       {} test [--max-steps <n>] [paths...]", program);
+    println!("       {} fmt [--check] [paths...]", program);
+    println!("       {} lint [-I <dir>] [paths...]", program);
+    println!("       {} cfg [--json] [-I <dir>] <program.dis>", program);
+    println!("       {} repl [-I <dir>] [program.dis]", program);
+    println!("Options:");
+    println!("  --profile                   print execution counts per label to stderr");
+    println!("  --profile-collapsed <file>  write collapsed stacks for flamegraph tools");
+    println!("  --coverage <file>           merge statement and branch coverage into an LCOV file");
+    println!("  --coverage-listing <file>   write the merged coverage as an annotated listing");
+    println!("  --memory <words>            memory size, up to 65536 (default 4096)");
+    println!("  --registers <n>             general purpose registers (default 8)");
+    println!("  --word-width <8|16|32>      bits per register and memory cell (default 16)");
+    println!("  -I <dir>                    search <dir> for includes, before DIS_PATH");
+    println!("  --ascii                     only allow ASCII chars instead of Unicode");
+    println!("  --device <name>@<addr>      map console, timer, random or keyboard into memory");
+    println!("  --dump-fb <file>            write the framebuffer as PPM, or PNG for .png files");
+    println!("  --dump-after <steps>        stop and dump after <steps> instead of at `die`");
+    println!("  --fb <addr>                 framebuffer address (default 1024)");
+    println!("  --fb-size <w>x<h>           framebuffer size in pixels (default 64x32)");
+    println!("  --fb-palette <palette>      c64, mono, gray or hex colors like 000000,ff0000");
+}
+
+fn create_device(name: &str) -> Option<Box<dyn Device>> {
+    match name {
+        "console" => Some(Box::new(Console::new())),
+        "timer" => Some(Box::new(Timer::new())),
+        "random" => Some(Box::new(Random::new())),
+        "keyboard" => {
+            let keyboard = Keyboard::new();
+            let keys = keyboard.keys();
+
+            // stdin bytes become key presses as they arrive
+            std::thread::spawn(move || {
+                for byte in BufReader::new(std::io::stdin()).bytes() {
+                    match byte {
+                        Ok(byte) => keys.lock().unwrap().push_back(byte.into()),
+                        Err(_) => break,
+                    }
+                }
+            });
+
+            Some(Box::new(keyboard))
+        }
+        _ => None,
+    }
+}
+
+fn test(program: &str, mut args: VecDeque<String>) -> ExitCode {
+    let mut max_steps = harness::DEFAULT_MAX_STEPS;
+    let mut paths = Vec::new();
+
+    while let Some(arg) = args.pop_front() {
+        match arg.as_str() {
+            "--max-steps" => match args.pop_front().map(|steps| steps.parse()) {
+                Some(Ok(steps)) => max_steps = steps,
+                _ => {
+                    eprintln!("expected number after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ if arg.starts_with("--") => {
+                eprintln!("unknown option `{arg}`");
+                usage(program);
+                return ExitCode::FAILURE;
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+
+    let cases: Vec<TestCase> = harness::discover(&paths)
+        .iter()
+        .filter_map(|path| TestCase::from_file(path, max_steps))
+        .collect();
+
+    let mut failed = 0;
+    for case in &cases {
+        match case.run() {
+            Outcome::Pass => println!("PASS {}", case.path.display()),
+            Outcome::Fail(reasons) => {
+                failed += 1;
+                println!("FAIL {}", case.path.display());
+                for reason in reasons {
+                    println!("    {reason}");
+                }
+            }
+        }
+    }
+
+    println!();
+    println!(
+        "test result: {} passed, {} failed",
+        cases.len() - failed,
+        failed
+    );
+
+    match failed {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    }
+}
+
+fn fmt(program: &str, mut args: VecDeque<String>) -> ExitCode {
+    let mut check = false;
+    let mut paths = Vec::new();
+
+    while let Some(arg) = args.pop_front() {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ if arg.starts_with("--") => {
+                eprintln!("unknown option `{arg}`");
+                usage(program);
+                return ExitCode::FAILURE;
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+
+    let mut failed = false;
+    for path in harness::discover(&paths) {
+        let Ok(source) = std::fs::read_to_string(&path) else {
+            eprintln!("Failed to read file: {}", path.display());
+            failed = true;
+            continue;
+        };
+
+        let Ok(formatted) = formatter::format_source(&path.to_string_lossy(), &source) else {
+            failed = true;
+            continue;
+        };
+
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            println!("would reformat {}", path.display());
+            failed = true;
+        } else if std::fs::write(&path, formatted).is_err() {
+            eprintln!("Failed to write file: {}", path.display());
+            failed = true;
+        }
+    }
+
+    match failed {
+        false => ExitCode::SUCCESS,
+        true => ExitCode::FAILURE,
+    }
+}
+
+fn lint(program: &str, mut args: VecDeque<String>) -> ExitCode {
+    let mut include_paths = Vec::new();
+    let mut paths = Vec::new();
+
+    while let Some(arg) = args.pop_front() {
+        match arg.as_str() {
+            "-I" => match args.pop_front() {
+                Some(dir) => include_paths.push(PathBuf::from(dir)),
+                None => {
+                    eprintln!("expected directory after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ if arg.starts_with("-") => {
+                eprintln!("unknown option `{arg}`");
+                usage(program);
+                return ExitCode::FAILURE;
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+
+    let mut failed = false;
+    let mut programs = Vec::new();
+    let mut included = HashSet::new();
+    for path in harness::discover(&paths) {
+        let mut dis = DIS::new();
+        dis.include_paths = include_paths.clone();
+
+        if dis.load(path.to_string_lossy()).is_err() {
+            failed = true;
+            continue;
+        }
+
+        let files = dis.includes.files().iter().skip(1);
+        included.extend(files.filter_map(|file| std::fs::canonicalize(file).ok()));
+        programs.push((path, dis));
+    }
+
+    // files included by several programs are only reported once
+    let mut reported = HashSet::new();
+    for (path, dis) in programs {
+        // included files are checked as part of the programs including them
+        if std::fs::canonicalize(&path).is_ok_and(|path| included.contains(&path)) {
+            continue;
+        }
+
+        for warning in lint::lint(&dis) {
+            if reported.insert((warning.loc.clone(), warning.rule)) {
+                println!("{warning}");
+            }
+            failed = true;
+        }
+    }
+
+    match failed {
+        false => ExitCode::SUCCESS,
+        true => ExitCode::FAILURE,
+    }
+}
+
+fn cfg(program: &str, mut args: VecDeque<String>) -> ExitCode {
+    let mut json = false;
+    let mut include_paths = Vec::new();
+    let mut path = None;
+
+    while let Some(arg) = args.pop_front() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "-I" => match args.pop_front() {
+                Some(dir) => include_paths.push(PathBuf::from(dir)),
+                None => {
+                    eprintln!("expected directory after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ if arg.starts_with("-") => {
+                eprintln!("unknown option `{arg}`");
+                usage(program);
+                return ExitCode::FAILURE;
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    let Some(path) = path else {
+        usage(program);
+        return ExitCode::FAILURE;
+    };
+
+    let mut dis = DIS::new();
+    dis.include_paths = include_paths;
+    if dis.load(&path).is_err() {
+        eprintln!("Error loading program");
+        return ExitCode::FAILURE;
+    }
+
+    let graph = Cfg::build(&dis.program);
+    match json {
+        #[cfg(feature = "json")]
+        true => print!("{}", graph.to_json(&dis.program)),
+        #[cfg(not(feature = "json"))]
+        true => {
+            eprintln!("`--json` needs dis built with `--features json`");
+            return ExitCode::FAILURE;
+        }
+        false => print!("{}", graph.to_dot(&dis.program, &path)),
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn repl(program: &str, mut args: VecDeque<String>) -> ExitCode {
+    let mut include_paths = Vec::new();
+    let mut path = None;
+
+    while let Some(arg) = args.pop_front() {
+        match arg.as_str() {
+            "-I" => match args.pop_front() {
+                Some(dir) => include_paths.push(PathBuf::from(dir)),
+                None => {
+                    eprintln!("expected directory after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ if arg.starts_with("-") => {
+                eprintln!("unknown option `{arg}`");
+                usage(program);
+                return ExitCode::FAILURE;
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    let mut dis = DIS::new();
+    dis.include_paths = include_paths;
+
+    let mut repl = Repl::new(dis);
+    let out = &mut std::io::stdout();
+    // reads wait for the next line, like the program was typed at
+    let mut inp = BufReader::new(std::io::stdin());
+    let interactive = std::io::stdin().is_terminal();
+
+    if let Some(path) = path {
+        repl.eval(&format!(":load {path}"), out, &mut inp);
+    }
+
+    if interactive {
+        println!("`:help` lists the commands");
+    }
+
+    while !repl.done {
+        if interactive {
+            print!("> ");
+            out.flush().expect("write error");
+        }
+
+        let mut line = String::new();
+        match inp.read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => repl.eval(&line, out, &mut inp),
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
 fn main() -> ExitCode {
     let mut args: VecDeque<String> = std::env::args().collect();
 
     let program = args.pop_front().unwrap();
 
-    if args.len() < 1 {
-        println!("Usage: {} <program.dis>", program);
-        return ExitCode::FAILURE;
+    if args.front().is_some_and(|arg| arg == "test") {
+        args.pop_front();
+        return test(&program, args);
     }
 
-    let filepath = args.pop_front().unwrap();
+    if args.front().is_some_and(|arg| arg == "fmt") {
+        args.pop_front();
+        return fmt(&program, args);
+    }
 
-    let mut dis = DIS::new();
+    if args.front().is_some_and(|arg| arg == "lint") {
+        args.pop_front();
+        return lint(&program, args);
+    }
+
+    if args.front().is_some_and(|arg| arg == "cfg") {
+        args.pop_front();
+        return cfg(&program, args);
+    }
+
+    if args.front().is_some_and(|arg| arg == "repl") {
+        args.pop_front();
+        return repl(&program, args);
+    }
+
+    if args.front().is_some_and(|arg| arg == "run") {
+        args.pop_front();
+    }
+
+    let mut profile = false;
+    let mut collapsed_path = None;
+    let mut lcov_path = None;
+    let mut listing_path = None;
+    let mut config = MachineConfig::new();
+    let mut devices = Vec::new();
+    let mut include_paths = Vec::new();
+    let mut fb = Framebuffer::new();
+    let mut fb_path = None;
+    let mut dump_after = None;
+    let mut filepath = None;
+
+    while let Some(arg) = args.pop_front() {
+        match arg.as_str() {
+            "--profile" => profile = true,
+            "--profile-collapsed" => match args.pop_front() {
+                Some(path) => collapsed_path = Some(path),
+                None => {
+                    eprintln!("expected file after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--coverage" => match args.pop_front() {
+                Some(path) => lcov_path = Some(path),
+                None => {
+                    eprintln!("expected file after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--coverage-listing" => match args.pop_front() {
+                Some(path) => listing_path = Some(path),
+                None => {
+                    eprintln!("expected file after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--memory" => match args.pop_front().map(|words| words.parse()) {
+                Some(Ok(words)) => config = config.memory_size(words),
+                _ => {
+                    eprintln!("expected number after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--registers" => match args.pop_front().map(|count| count.parse()) {
+                Some(Ok(count)) => config = config.registers(count),
+                _ => {
+                    eprintln!("expected number after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-I" => match args.pop_front() {
+                Some(dir) => include_paths.push(PathBuf::from(dir)),
+                None => {
+                    eprintln!("expected directory after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--ascii" => config = config.ascii(true),
+            "--device" => {
+                let device = args.pop_front().and_then(|spec| {
+                    let (name, addr) = spec.split_once('@')?;
+                    Some((name.to_string(), parse_address(addr)?))
+                });
+
+                match device {
+                    Some(device) => devices.push(device),
+                    None => {
+                        eprintln!("expected <name>@<address> after `{arg}`");
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            "--dump-fb" => match args.pop_front() {
+                Some(path) => fb_path = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("expected file after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--dump-after" => match args.pop_front().map(|steps| steps.parse()) {
+                Some(Ok(steps)) => dump_after = Some(steps),
+                _ => {
+                    eprintln!("expected number after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--fb" => match args.pop_front().and_then(|addr| parse_address(&addr)) {
+                Some(addr) => fb = fb.base(addr),
+                None => {
+                    eprintln!("expected address after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--fb-size" => match args.pop_front().and_then(|s| Framebuffer::parse_size(&s)) {
+                Some((width, height)) => fb = fb.size(width, height),
+                None => {
+                    eprintln!("expected <width>x<height> after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--fb-palette" => match args.pop_front().and_then(|s| Palette::parse(&s)) {
+                Some(palette) => fb = fb.palette(palette),
+                None => {
+                    eprintln!("expected palette after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--word-width" => {
+                let width = args
+                    .pop_front()
+                    .and_then(|bits| bits.parse().ok())
+                    .and_then(WordWidth::from_bits);
+
+                match width {
+                    Some(width) => config = config.word_width(width),
+                    None => {
+                        eprintln!("expected 8, 16 or 32 after `{arg}`");
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            _ if arg.starts_with("--") => {
+                eprintln!("unknown option `{arg}`");
+                usage(&program);
+                return ExitCode::FAILURE;
+            }
+            _ => filepath = Some(arg),
+        }
+    }
+
+    let filepath = match filepath {
+        Some(filepath) => filepath,
+        None => {
+            usage(&program);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut dis = match DIS::new_with(config) {
+        Ok(dis) => dis,
+        Err(_) => return ExitCode::FAILURE,
+    };
+
+    dis.include_paths = include_paths;
+
+    for (name, addr) in devices {
+        let Some(device) = create_device(&name) else {
+            eprintln!("unknown device `{name}`");
+            return ExitCode::FAILURE;
+        };
+
+        if dis.attach_device(addr, device).is_err() {
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if fb_path.is_some() && fb.validate(&dis).is_err() {
+        return ExitCode::FAILURE;
+    }
 
     if dis.load(filepath).is_err() {
         println!("Error loading program");
         return ExitCode::FAILURE;
     }
 
-    dis.run();
+    if profile || collapsed_path.is_some() {
+        dis.profiler = Some(Profiler::new());
+    }
 
-    ExitCode::SUCCESS
+    if lcov_path.is_some() || listing_path.is_some() {
+        dis.coverage = Some(Coverage::new(&dis.program));
+    }
+
+    let exit_code = match dump_after {
+        Some(steps) => dis.run_for(steps),
+        None => dis.run(),
+    };
+
+    if let Some(path) = &fb_path {
+        if let Err(err) = fb.dump(&dis, path) {
+            eprintln!("Failed to write file: {}: {err}", path.display());
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(profiler) = &dis.profiler {
+        if profile {
+            profiler
+                .report(&dis.program, &mut std::io::stderr())
+                .expect("write error");
+        }
+
+        if let Some(path) = collapsed_path {
+            let mut file = match File::create(&path) {
+                Ok(file) => file,
+                Err(err) => {
+                    eprintln!("Failed to create file: {path}: {err}");
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            profiler
+                .write_collapsed(&dis.program, &mut file)
+                .expect("write error");
+        }
+    }
+
+    if let Some(coverage) = &mut dis.coverage {
+        if let Some(path) = &lcov_path {
+            if let Ok(file) = File::open(path) {
+                if let Err(err) = coverage.read_lcov(&mut BufReader::new(file)) {
+                    eprintln!("Failed to read file: {path}: {err}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+
+        for (path, listing) in [(lcov_path, false), (listing_path, true)] {
+            let Some(path) = path else {
+                continue;
+            };
+
+            let mut file = match File::create(&path) {
+                Ok(file) => file,
+                Err(err) => {
+                    eprintln!("Failed to create file: {path}: {err}");
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let result = match listing {
+                true => coverage.write_listing(&mut file),
+                false => coverage.write_lcov(&mut file),
+            };
+            result.expect("write error");
+        }
+    }
+
+    // exit statuses are a single byte, larger codes must not read as success
+    ExitCode::from(u8::try_from(exit_code).unwrap_or(u8::MAX))
 }
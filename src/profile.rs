@@ -0,0 +1,211 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use crate::statement::Statement;
+
+/// Counts executed statements together with the `run` stack they were
+/// executed under.
+///
+/// Every `run` target is treated as a function, the program entry is `main`.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    /// function entry indices followed by the executed statement index
+    samples: HashMap<Vec<usize>, u64>,
+    steps: u64,
+}
+
+const MAIN: &str = "main";
+const NO_LABEL: &str = "<no label>";
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        pc: usize,
+        return_stack: &[usize],
+        program: &[Statement],
+        label_map: &HashMap<String, usize>,
+    ) {
+        let mut key = Vec::with_capacity(return_stack.len() + 1);
+
+        for &ret in return_stack {
+            let entry = program[ret]
+                .target()
+                .and_then(|label| label_map.get(label))
+                .copied()
+                .unwrap_or(ret);
+            key.push(entry);
+        }
+        key.push(pc);
+
+        *self.samples.entry(key).or_insert(0) += 1;
+        self.steps += 1;
+    }
+
+    /// execution count of every statement
+    pub fn statement_counts(&self) -> HashMap<usize, u64> {
+        let mut counts = HashMap::new();
+        for (key, count) in &self.samples {
+            *counts.entry(*key.last().unwrap()).or_insert(0) += count;
+        }
+
+        counts
+    }
+
+    /// counts per label enclosing the executed statement
+    fn label_counts<'a>(&self, program: &'a [Statement]) -> BTreeMap<&'a str, u64> {
+        let enclosing = enclosing_labels(program);
+        let mut counts = BTreeMap::new();
+
+        for (key, count) in &self.samples {
+            let pc = *key.last().unwrap();
+            let label = enclosing.get(pc).copied().flatten().unwrap_or(NO_LABEL);
+            *counts.entry(label).or_insert(0) += count;
+        }
+
+        counts
+    }
+
+    /// `(inclusive, exclusive)` counts per function
+    fn function_counts<'a>(&self, program: &'a [Statement]) -> BTreeMap<&'a str, (u64, u64)> {
+        let mut counts: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+
+        for (key, count) in &self.samples {
+            let frames = &key[..key.len() - 1];
+
+            let mut seen = vec![MAIN];
+            counts.entry(MAIN).or_default().0 += count;
+
+            for &entry in frames {
+                let name = function_name(program, entry);
+                if !seen.contains(&name) {
+                    seen.push(name);
+                    counts.entry(name).or_default().0 += count;
+                }
+            }
+
+            let current = frames
+                .last()
+                .map(|&entry| function_name(program, entry))
+                .unwrap_or(MAIN);
+            counts.entry(current).or_default().1 += count;
+        }
+
+        counts
+    }
+
+    pub fn report(&self, program: &[Statement], out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "profile: {} steps", self.steps)?;
+        if self.steps == 0 {
+            return Ok(());
+        }
+
+        let percent = |count: u64| count as f64 * 100.0 / self.steps as f64;
+
+        writeln!(out)?;
+        writeln!(out, "{:<32} {:>10} {:>7}", "label", "self", "%")?;
+        let mut labels: Vec<_> = self.label_counts(program).into_iter().collect();
+        labels.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        for (label, count) in labels {
+            writeln!(out, "{:<32} {:>10} {:>6.2}%", label, count, percent(count))?;
+        }
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{:<32} {:>10} {:>7} {:>10} {:>7}",
+            "function", "inclusive", "%", "exclusive", "%"
+        )?;
+        let mut functions: Vec<_> = self.function_counts(program).into_iter().collect();
+        functions.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.0.cmp(b.0)));
+        for (name, (inclusive, exclusive)) in functions {
+            writeln!(
+                out,
+                "{:<32} {:>10} {:>6.2}% {:>10} {:>6.2}%",
+                name,
+                inclusive,
+                percent(inclusive),
+                exclusive,
+                percent(exclusive)
+            )?;
+        }
+
+        writeln!(out)?;
+        writeln!(out, "{:<32} {:>10} {:>7}  op", "statement", "count", "%")?;
+        let mut statements: Vec<_> = self.statement_counts().into_iter().collect();
+        statements.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for (idx, count) in statements.into_iter().take(10) {
+            let statement = &program[idx];
+            let loc = match statement.op.token() {
+                Some(token) => token.loc().to_string(),
+                None => format!("#{idx}"),
+            };
+            writeln!(
+                out,
+                "{:<32} {:>10} {:>6.2}%  {}",
+                loc,
+                count,
+                percent(count),
+                statement.op.mnemonic()
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// writes one `main;fn;label count` line per stack, the format read by
+    /// `flamegraph.pl` and `inferno-flamegraph`
+    pub fn write_collapsed(&self, program: &[Statement], out: &mut dyn Write) -> io::Result<()> {
+        let enclosing = enclosing_labels(program);
+        let mut stacks: BTreeMap<String, u64> = BTreeMap::new();
+
+        for (key, count) in &self.samples {
+            let (pc, frames) = key.split_last().unwrap();
+
+            let mut stack = vec![MAIN];
+            stack.extend(frames.iter().map(|&entry| function_name(program, entry)));
+
+            if let Some(label) = enclosing.get(*pc).copied().flatten() {
+                if stack.last() != Some(&label) {
+                    stack.push(label);
+                }
+            }
+
+            *stacks.entry(stack.join(";")).or_insert(0) += count;
+        }
+
+        for (stack, count) in stacks {
+            writeln!(out, "{stack} {count}")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn function_name(program: &[Statement], entry: usize) -> &str {
+    program
+        .get(entry)
+        .and_then(|statement| statement.label_name())
+        .unwrap_or(NO_LABEL)
+}
+
+/// closest label at or before every statement
+fn enclosing_labels(program: &[Statement]) -> Vec<Option<&str>> {
+    let mut current = None;
+    program
+        .iter()
+        .map(|statement| {
+            if let Some(label) = statement.label_name() {
+                current = Some(label);
+            }
+            current
+        })
+        .collect()
+}
@@ -0,0 +1,71 @@
+use std::io::{BufRead, Write};
+
+use serde_json::Value;
+
+/// JSON-RPC error code for a message that is not valid JSON
+pub const PARSE_ERROR: i64 = -32700;
+
+/// A message that could not be parsed, reading goes on after it.
+#[derive(Debug, Clone)]
+pub struct Malformed {
+    /// the body as it was read, empty without a `Content-Length`
+    pub body: String,
+    pub error: String,
+}
+
+impl Malformed {
+    /// value of a top level `"field": value` found in the body, for
+    /// answering the request the message was meant to be
+    pub fn field(&self, name: &str) -> Option<Value> {
+        let key = format!("\"{name}\"");
+        let start = self.body.find(&key)? + key.len();
+        let rest = self.body[start..].trim_start().strip_prefix(':')?;
+        let end = rest.find([',', '}']).unwrap_or(rest.len());
+        serde_json::from_str(rest[..end].trim()).ok()
+    }
+}
+
+/// Reads a `Content-Length` framed JSON message, the framing LSP and DAP
+/// use over stdio. `None` once the input is closed.
+pub fn read_message(input: &mut impl BufRead) -> Option<Result<Value, Malformed>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let Some(length) = length else {
+        return Some(Err(Malformed {
+            body: String::new(),
+            error: "missing `Content-Length` header".to_string(),
+        }));
+    };
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).ok()?;
+
+    Some(serde_json::from_slice(&body).map_err(|err| Malformed {
+        body: String::from_utf8_lossy(&body).to_string(),
+        error: err.to_string(),
+    }))
+}
+
+pub fn write_message(out: &mut impl Write, message: &Value) {
+    let body = message.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body).expect("write error");
+    out.flush().expect("write error");
+}
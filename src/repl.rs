@@ -0,0 +1,281 @@
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::include::IncludeGraph;
+use crate::input::Input;
+use crate::lexer::{Lexer, Token};
+use crate::statement::{Op, Statement};
+use crate::{Result, DIS};
+
+/// steps a line can run before it is stopped, it can go on with `:step`
+pub const MAX_STEPS: usize = 1_000_000;
+
+pub const HELP: &str = "\
+instructions run as soon as they are entered, labels can be jumped to later
+:regs             show the registers and the `><=` bits
+:mem <addr> [n]   show n words of memory (16 by default)
+:load <file>      load a program, its labels can be jumped to and `run`
+:reset            clear the machine and everything entered
+:step [n]         run n statements (1 by default) from where the machine is
+:help             show this
+:quit             leave";
+
+/// `out` that remembers whether the last thing written ended a line
+struct Output<'a> {
+    out: &'a mut dyn Write,
+    line_open: bool,
+}
+
+impl Write for Output<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(&last) = buf.last() {
+            self.line_open = last != b'\n';
+        }
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// A machine that is fed statements one line at a time.
+///
+/// Entered statements are added to the end of the program and run from
+/// there until the program ends again, so jumping back to an earlier label
+/// runs everything after it once more.
+pub struct Repl {
+    pub dis: DIS,
+    /// whether `:quit` was entered
+    pub done: bool,
+}
+
+impl Repl {
+    pub fn new(dis: DIS) -> Self {
+        Repl { dis, done: false }
+    }
+
+    /// runs a line of statements or a `:` command
+    pub fn eval(&mut self, line: &str, out: &mut dyn Write, inp: &mut dyn Input) {
+        let mut out = Output {
+            out,
+            line_open: false,
+        };
+
+        match line.trim().strip_prefix(':') {
+            Some(command) => self.command(command, &mut out, inp),
+            None => {
+                if let Ok(start) = self.add(line) {
+                    self.dis.pc = start;
+                    if self.run(MAX_STEPS, &mut out, inp) {
+                        let at = self.statement_at(self.dis.pc);
+                        writeln!(
+                            out,
+                            "stopped after {MAX_STEPS} steps before {at}, `:step` goes on"
+                        )
+                        .expect("write error");
+                    }
+                }
+            }
+        }
+
+        if out.line_open {
+            writeln!(out).expect("write error");
+        }
+    }
+
+    /// parses the statements of `line` onto the program, returns where
+    /// they start
+    fn add(&mut self, line: &str) -> Result<usize> {
+        let mut tokens = Lexer::from_source("<repl>".to_string(), line.to_string()).tokens()?;
+
+        let mut statements = Vec::new();
+        while let Some(statement) = Statement::parse(&mut tokens)? {
+            statements.push(statement);
+        }
+
+        let start = self.dis.program.len();
+        let mut labels = self.dis.label_map.clone();
+        for (offset, statement) in statements.iter().enumerate() {
+            if let Op::INC(token) | Op::EXPORT(token) = &statement.op {
+                let mnemonic = statement.op.mnemonic();
+                error!(
+                    token.loc(),
+                    "`{mnemonic}` is not available here, use `:load`"
+                );
+                return Err(());
+            }
+
+            statement.validate(&self.dis.config)?;
+
+            if let Some(Token::Label { value, loc }) = &statement.label {
+                if labels.contains_key(value) {
+                    error!(loc, "duplicate label `{value}`");
+                    return Err(());
+                }
+                labels.insert(value.clone(), start + offset);
+            }
+        }
+
+        for statement in &statements {
+            let Some(operand) = statement.target_operand() else {
+                continue;
+            };
+
+            if let Token::Identifier { value, loc } = &statement.body[operand] {
+                if !labels.contains_key(value) {
+                    error!(loc, "undefined label `{value}`");
+                    return Err(());
+                }
+            }
+        }
+
+        self.dis.label_map = labels;
+        self.dis.program.extend(statements);
+
+        Ok(start)
+    }
+
+    /// steps until the program ends, dies or `steps` ran out, true when
+    /// there is more to run
+    fn run(&mut self, steps: usize, out: &mut dyn Write, inp: &mut dyn Input) -> bool {
+        let dis = &mut self.dis;
+
+        // a panic is a bug in the machine, it ends the program and not the
+        // session
+        let ran = panic::catch_unwind(AssertUnwindSafe(|| {
+            for _ in 0..steps {
+                if dis.die || dis.pc >= dis.program.len() {
+                    return true;
+                }
+                dis.step(out, inp);
+            }
+            dis.die || dis.pc >= dis.program.len()
+        }));
+
+        let more = match ran {
+            Ok(done) => !done,
+            Err(_) => {
+                error!("runtime error at {}", self.statement_at(self.dis.pc));
+                self.dis.exit_code = 1;
+                self.dis.die = true;
+                false
+            }
+        };
+
+        // the machine outlives the program
+        if self.dis.die {
+            writeln!(out, "exited with code {}", self.dis.exit_code).expect("write error");
+            self.dis.die = false;
+            self.dis.exit_code = 0;
+            self.dis.pc = self.dis.program.len();
+        }
+
+        more
+    }
+
+    /// statement `idx` as it is written
+    fn statement_at(&self, idx: usize) -> String {
+        match self.dis.program.get(idx) {
+            Some(statement) if matches!(statement.op, Op::NOP) => {
+                format!("{}:", statement.label_name().unwrap_or_default())
+            }
+            Some(statement) => format!("`{}`", statement.text()),
+            None => "the end".to_string(),
+        }
+    }
+
+    fn command(&mut self, command: &str, out: &mut Output, inp: &mut dyn Input) {
+        let mut args = command.split_whitespace();
+        let name = args.next().unwrap_or_default();
+        let args: Vec<&str> = args.collect();
+
+        let number = |arg: Option<&&str>, default: usize| match arg {
+            Some(arg) => arg.parse().ok(),
+            None => Some(default),
+        };
+
+        match (name, args.as_slice()) {
+            ("regs", []) => {
+                let registers: Vec<String> = self
+                    .dis
+                    .config
+                    .register_names()
+                    .into_iter()
+                    .map(|name| format!("#{name} {}", self.dis.registers[&name]))
+                    .collect();
+                writeln!(out, "{}", registers.join("  ")).expect("write error");
+                writeln!(out, "cmp {}", self.dis.flags()).expect("write error");
+            }
+
+            ("mem", [addr, ..]) if args.len() <= 2 => {
+                let (Ok(addr), Some(count)) = (addr.parse::<usize>(), number(args.get(1), 16))
+                else {
+                    error!("expected `:mem <addr> [n]`");
+                    return;
+                };
+
+                if addr >= self.dis.memory.len() {
+                    error!("address {addr} is out of memory");
+                    return;
+                }
+
+                let end = addr.saturating_add(count).min(self.dis.memory.len());
+                for row in (addr..end).step_by(8) {
+                    let words: Vec<String> = self.dis.memory[row..(row + 8).min(end)]
+                        .iter()
+                        .map(|word| format!("{word:>5}"))
+                        .collect();
+                    writeln!(out, "&{row:<5}{}", words.join(" ")).expect("write error");
+                }
+            }
+
+            ("load", [path]) => {
+                if self.dis.load(*path).is_err() {
+                    error!("cannot load `{path}`");
+                    self.reset();
+                    return;
+                }
+
+                let labels = self.dis.label_map.len();
+                writeln!(
+                    out,
+                    "loaded {} statements, {labels} labels",
+                    self.dis.program.len()
+                )
+                .expect("write error");
+            }
+
+            ("reset", []) => self.reset(),
+
+            ("step", [] | [_]) => {
+                let Some(count) = number(args.first(), 1) else {
+                    error!("expected `:step [n]`");
+                    return;
+                };
+
+                if self.dis.pc >= self.dis.program.len() {
+                    writeln!(out, "nothing to step").expect("write error");
+                    return;
+                }
+
+                self.run(count, out, inp);
+                if out.line_open {
+                    writeln!(out).expect("write error");
+                }
+                writeln!(out, "next: {}", self.statement_at(self.dis.pc)).expect("write error");
+            }
+
+            ("help", []) => writeln!(out, "{HELP}").expect("write error"),
+
+            ("quit" | "q", []) => self.done = true,
+
+            _ => error!("unknown command `:{command}`, `:help` lists them"),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.dis.reset();
+        self.dis.includes = IncludeGraph::new();
+    }
+}
@@ -1,3 +1,4 @@
+use crate::config::{MachineConfig, Word};
 use crate::lexer::Token;
 
 use crate::Result;
@@ -23,9 +24,106 @@ pub enum Op {
     RDN(Token),
     RDC(Token),
     RLN(Token),
+    SYS(Token),
+    IVT(Token),
+    EI(Token),
+    DI(Token),
+    IRET(Token),
+    TMR(Token),
+    RDK(Token),
+    OTS(Token),
+    LEN(Token),
+    CPY(Token),
+    CMPS(Token),
+    PRX(Token),
+    PRB(Token),
+    PRS(Token),
+    EXPORT(Token),
     NOP,
 }
 
+impl Op {
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            Op::MOV(token)
+            | Op::ADD(token)
+            | Op::SUB(token)
+            | Op::CMP(token)
+            | Op::JLT(token)
+            | Op::JGT(token)
+            | Op::JEQ(token)
+            | Op::JNE(token)
+            | Op::JMP(token)
+            | Op::RUN(token)
+            | Op::RET(token)
+            | Op::DIE(token)
+            | Op::OUT(token)
+            | Op::PRT(token)
+            | Op::DBG(token)
+            | Op::INC(token)
+            | Op::RDN(token)
+            | Op::RDC(token)
+            | Op::RLN(token)
+            | Op::SYS(token)
+            | Op::IVT(token)
+            | Op::EI(token)
+            | Op::DI(token)
+            | Op::IRET(token)
+            | Op::TMR(token)
+            | Op::RDK(token)
+            | Op::OTS(token)
+            | Op::LEN(token)
+            | Op::CPY(token)
+            | Op::CMPS(token)
+            | Op::PRX(token)
+            | Op::PRB(token)
+            | Op::PRS(token)
+            | Op::EXPORT(token) => Some(token),
+            Op::NOP => None,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Op::MOV(_) => "mov",
+            Op::ADD(_) => "add",
+            Op::SUB(_) => "sub",
+            Op::CMP(_) => "cmp",
+            Op::JLT(_) => "jlt",
+            Op::JGT(_) => "jgt",
+            Op::JEQ(_) => "jeq",
+            Op::JNE(_) => "jne",
+            Op::JMP(_) => "jmp",
+            Op::RUN(_) => "run",
+            Op::RET(_) => "ret",
+            Op::DIE(_) => "die",
+            Op::OUT(_) => "out",
+            Op::PRT(_) => "prt",
+            Op::DBG(_) => "dbg",
+            Op::INC(_) => "@",
+            Op::RDN(_) => "rdn",
+            Op::RDC(_) => "rdc",
+            Op::RLN(_) => "rln",
+            Op::SYS(_) => "sys",
+            Op::IVT(_) => "ivt",
+            Op::EI(_) => "ei",
+            Op::DI(_) => "di",
+            Op::IRET(_) => "iret",
+            Op::TMR(_) => "tmr",
+            Op::RDK(_) => "rdk",
+            Op::OTS(_) => "ots",
+            Op::LEN(_) => "len",
+            Op::CPY(_) => "cpy",
+            Op::CMPS(_) => "cmps",
+            Op::PRX(_) => "prx",
+            Op::PRB(_) => "prb",
+            Op::PRS(_) => "prs",
+            Op::EXPORT(_) => "export",
+            Op::NOP => "nop",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Statement {
     pub label: Option<Token>,
@@ -34,6 +132,147 @@ pub struct Statement {
 }
 
 impl Statement {
+    /// label a jump, `run` or `ivt` goes to
+    fn label_operand(token: Token) -> Result<Token> {
+        match token {
+            Token::Identifier { .. } => Ok(token),
+            other => {
+                error!(other.loc(), "expected label identifier, found `{other}`");
+                Err(())
+            }
+        }
+    }
+
+    /// optional field width after a number to print, `w4` pads with spaces and
+    /// `z4` with zeros, it is on the same line as the `op` it belongs to
+    fn parse_format(tokens: &mut Vec<Token>, op: &Op) -> Result<Option<Token>> {
+        let Some(Token::Identifier { value, loc }) = tokens.first() else {
+            return Ok(None);
+        };
+
+        let same_line = op
+            .token()
+            .is_some_and(|token| token.loc().line() == loc.line());
+        let digits = value.strip_prefix(['w', 'z']).unwrap_or_default();
+        let width = !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit());
+
+        // anything else is left for the next statement to complain about
+        if !same_line || !width {
+            return Ok(None);
+        }
+
+        if digits.parse::<u8>().is_err() {
+            error!(loc, "width `{value}` is too large, at most {}", u8::MAX);
+            return Err(());
+        }
+
+        Ok(Some(tokens.remove(0)))
+    }
+
+    pub fn label_name(&self) -> Option<&str> {
+        match &self.label {
+            Some(Token::Label { value, .. }) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// file the statement was read from
+    pub fn file(&self) -> &str {
+        match (&self.label, self.op.token()) {
+            (Some(label), _) => label.loc().file(),
+            (None, Some(token)) => token.loc().file(),
+            (None, None) => "",
+        }
+    }
+
+    /// index into `body` of the label a jump, `run` or `ivt` targets
+    pub fn target_operand(&self) -> Option<usize> {
+        match &self.op {
+            Op::JEQ(_) | Op::JNE(_) | Op::JLT(_) | Op::JGT(_) | Op::JMP(_) | Op::RUN(_) => Some(0),
+            Op::IVT(_) => Some(1),
+            _ => None,
+        }
+    }
+
+    /// label targeted by a jump, `run` or `ivt`
+    pub fn target(&self) -> Option<&str> {
+        match &self.body[self.target_operand()?] {
+            Token::Identifier { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    /// the instruction as it is written in source, without its label
+    pub fn text(&self) -> String {
+        std::iter::once(self.op.mnemonic().to_string())
+            .chain(self.body.iter().map(Token::text))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// checks operands against the machine the program is loaded into,
+    /// reporting every problem found
+    pub fn validate(&self, config: &MachineConfig) -> Result<()> {
+        let mask = config.word_width.mask();
+        let mut valid = true;
+
+        for token in &self.body {
+            match token {
+                Token::Register { value, loc } if !config.has_register(value) => {
+                    error!(loc, "unknown register `#{value}`");
+                    valid = false;
+                }
+                Token::Memory { value, loc } => match value.strip_prefix('#') {
+                    Some(reg) => {
+                        if !config.has_register(reg) {
+                            error!(loc, "unknown register `#{reg}` in `&{value}`");
+                            valid = false;
+                        }
+                    }
+                    None => match value.parse::<usize>() {
+                        Ok(addr) if addr >= config.memory_size => {
+                            error!(
+                                loc,
+                                "address `&{value}` is outside memory ({} words)",
+                                config.memory_size
+                            );
+                            valid = false;
+                        }
+                        Ok(_) => {}
+                        Err(_) => {
+                            error!(loc, "invalid memory address `&{value}`");
+                            valid = false;
+                        }
+                    },
+                },
+                Token::Number { value, loc } if *value > mask => {
+                    error!(
+                        loc,
+                        "number `{value}` is out of range for {} words", config.word_width
+                    );
+                    valid = false;
+                }
+                Token::Char { value, loc } if config.ascii && !value.is_ascii() => {
+                    error!(loc, "char `{value}` is not ASCII");
+                    valid = false;
+                }
+                Token::Char { value, loc } if *value as Word > mask => {
+                    error!(
+                        loc,
+                        "char `{value}` is out of range for {} words", config.word_width
+                    );
+                    valid = false;
+                }
+                _ => {}
+            }
+        }
+
+        match valid {
+            true => Ok(()),
+            false => Err(()),
+        }
+    }
+
     pub fn parse(tokens: &mut Vec<Token>) -> Result<Option<Self>> {
         if tokens.is_empty() {
             return Ok(None);
@@ -49,6 +288,12 @@ impl Statement {
 
         if let Token::Label { .. } = &token {
             statement.label = Some(token.clone());
+
+            // label at the end of the program
+            if tokens.is_empty() {
+                return Ok(Some(statement));
+            }
+
             token = tokens.remove(0);
         }
 
@@ -56,7 +301,7 @@ impl Statement {
             Token::Keyword { value, loc } => match value.as_str() {
                 "mov" => {
                     if tokens.len() < 2 {
-                        eprintln!("{loc}: expected two operands for `mov`", loc = loc);
+                        error!(loc, "expected two operands for `mov`");
                         return Err(());
                     }
 
@@ -68,7 +313,10 @@ impl Statement {
                             Token::Memory { .. } => Ok(op),
                             Token::Char { .. } => Ok(op),
                             other => {
-                                eprintln!("{loc}: expected number, register, memory or char, found `{other}`", loc = other.loc());
+                                error!(
+                                    other.loc(),
+                                    "expected number, register, memory or char, found `{other}`"
+                                );
                                 Err(())
                             }
                         }
@@ -79,10 +327,7 @@ impl Statement {
                             Token::Register { .. } => Ok(op),
                             Token::Memory { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected register or memory, found `{other}`",
-                                    loc = other.loc()
-                                );
+                                error!(other.loc(), "expected register or memory, found `{other}`");
                                 Err(())
                             }
                         }
@@ -96,7 +341,7 @@ impl Statement {
                 }
                 "add" => {
                     if tokens.len() < 2 {
-                        eprintln!("{loc}: expected two operands for `add`", loc = loc);
+                        error!(loc, "expected two operands for `add`");
                         return Err(());
                     }
 
@@ -108,7 +353,10 @@ impl Statement {
                             Token::Memory { .. } => Ok(op),
                             Token::Char { .. } => Ok(op),
                             other => {
-                                eprintln!("{loc}: expected number, register, memory or char, found `{other}`", loc = other.loc());
+                                error!(
+                                    other.loc(),
+                                    "expected number, register, memory or char, found `{other}`"
+                                );
                                 Err(())
                             }
                         }
@@ -119,10 +367,7 @@ impl Statement {
                             Token::Register { .. } => Ok(op),
                             Token::Memory { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected register or memory, found `{other}`",
-                                    loc = other.loc()
-                                );
+                                error!(other.loc(), "expected register or memory, found `{other}`");
                                 Err(())
                             }
                         }
@@ -136,7 +381,7 @@ impl Statement {
                 }
                 "sub" => {
                     if tokens.len() < 2 {
-                        eprintln!("{loc}: expected two operands for `sub`", loc = loc);
+                        error!(loc, "expected two operands for `sub`");
                         return Err(());
                     }
 
@@ -148,7 +393,10 @@ impl Statement {
                             Token::Memory { .. } => Ok(op),
                             Token::Char { .. } => Ok(op),
                             other => {
-                                eprintln!("{loc}: expected number, register, memory or char, found `{other}`", loc = other.loc());
+                                error!(
+                                    other.loc(),
+                                    "expected number, register, memory or char, found `{other}`"
+                                );
                                 Err(())
                             }
                         }
@@ -160,10 +408,7 @@ impl Statement {
                             Token::Register { .. } => Ok(op),
                             Token::Memory { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected register or memory, found `{other}`",
-                                    loc = other.loc()
-                                );
+                                error!(other.loc(), "expected register or memory, found `{other}`");
                                 Err(())
                             }
                         }
@@ -177,7 +422,7 @@ impl Statement {
                 }
                 "cmp" => {
                     if tokens.len() < 2 {
-                        eprintln!("{loc}: expected two operands for `cmp`", loc = loc);
+                        error!(loc, "expected two operands for `cmp`");
                         return Err(());
                     }
 
@@ -189,7 +434,10 @@ impl Statement {
                             Token::Memory { .. } => Ok(op),
                             Token::Char { .. } => Ok(op),
                             other => {
-                                eprintln!("{loc}: expected number, register, memory or char, found `{other}`", loc = other.loc());
+                                error!(
+                                    other.loc(),
+                                    "expected number, register, memory or char, found `{other}`"
+                                );
                                 Err(())
                             }
                         }
@@ -200,10 +448,7 @@ impl Statement {
                             Token::Register { .. } => Ok(op),
                             Token::Memory { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected register or memory, found `{other}`",
-                                    loc = other.loc()
-                                );
+                                error!(other.loc(), "expected register or memory, found `{other}`");
                                 Err(())
                             }
                         }
@@ -217,82 +462,192 @@ impl Statement {
                 }
                 "jlt" => {
                     if tokens.len() < 1 {
-                        eprintln!("{loc}: expected one operand for `jlt`", loc = loc);
+                        error!(loc, "expected one operand for `jlt`");
+                        return Err(());
+                    }
+
+                    let op1 = Statement::label_operand(tokens.remove(0))?;
+
+                    statement.op = Op::JLT(token);
+                    statement.body.push(op1);
+
+                    return Ok(Some(statement));
+                }
+                "jgt" => {
+                    if tokens.len() < 1 {
+                        error!(loc, "expected one operand for `jgt`");
+                        return Err(());
+                    }
+
+                    let op1 = Statement::label_operand(tokens.remove(0))?;
+
+                    statement.op = Op::JGT(token);
+                    statement.body.push(op1);
+
+                    return Ok(Some(statement));
+                }
+                "jeq" => {
+                    if tokens.len() < 1 {
+                        error!(loc, "expected one operand for `jeq`");
+                        return Err(());
+                    }
+
+                    let op1 = Statement::label_operand(tokens.remove(0))?;
+
+                    statement.op = Op::JEQ(token);
+                    statement.body.push(op1);
+
+                    return Ok(Some(statement));
+                }
+                "jne" => {
+                    if tokens.len() < 1 {
+                        error!(loc, "expected one operand for `jne`");
+                        return Err(());
+                    }
+
+                    let op1 = Statement::label_operand(tokens.remove(0))?;
+
+                    statement.op = Op::JNE(token);
+                    statement.body.push(op1);
+
+                    return Ok(Some(statement));
+                }
+                "jmp" => {
+                    if tokens.len() < 1 {
+                        error!(loc, "expected one operand for `jmp`");
+                        return Err(());
+                    }
+
+                    let op1 = Statement::label_operand(tokens.remove(0))?;
+
+                    statement.op = Op::JMP(token);
+                    statement.body.push(op1);
+
+                    return Ok(Some(statement));
+                }
+                "run" => {
+                    if tokens.len() < 1 {
+                        error!(loc, "expected one operand for `run`");
+                        return Err(());
+                    }
+
+                    let op1 = Statement::label_operand(tokens.remove(0))?;
+
+                    statement.op = Op::RUN(token);
+                    statement.body.push(op1);
+
+                    return Ok(Some(statement));
+                }
+                "ret" => {
+                    statement.op = Op::RET(token);
+                    return Ok(Some(statement));
+                }
+                "die" => {
+                    // optional exit code, statements never start with a value
+                    if let Some(
+                        Token::Number { .. }
+                        | Token::Register { .. }
+                        | Token::Memory { .. }
+                        | Token::Char { .. },
+                    ) = tokens.first()
+                    {
+                        statement.body.push(tokens.remove(0));
+                    }
+
+                    statement.op = Op::DIE(token);
+                    return Ok(Some(statement));
+                }
+                "out" => {
+                    if tokens.len() < 1 {
+                        error!(loc, "expected one operand for `out`");
                         return Err(());
                     }
 
                     let op1 = {
                         let op = tokens.remove(0);
                         match op {
-                            Token::Identifier { .. } => Ok(op),
+                            Token::Number { .. } => Ok(op),
+                            Token::Register { .. } => Ok(op),
+                            Token::Memory { .. } => Ok(op),
+                            Token::Char { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected label identifier, found `{other}`",
-                                    loc = other.loc()
+                                error!(
+                                    other.loc(),
+                                    "expected number, register, memory or char, found `{other}`"
                                 );
                                 Err(())
                             }
                         }
                     }?;
 
-                    statement.op = Op::JLT(token);
+                    statement.op = Op::OUT(token);
                     statement.body.push(op1);
 
                     return Ok(Some(statement));
                 }
-                "jgt" => {
+                "prt" => {
                     if tokens.len() < 1 {
-                        eprintln!("{loc}: expected one operand for `jgt`", loc = loc);
+                        error!(loc, "expected one operand for `prt`");
                         return Err(());
                     }
 
                     let op1 = {
                         let op = tokens.remove(0);
                         match op {
-                            Token::Identifier { .. } => Ok(op),
+                            Token::Number { .. } => Ok(op),
+                            Token::Register { .. } => Ok(op),
+                            Token::Memory { .. } => Ok(op),
+                            Token::Char { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected label identifier, found `{other}`",
-                                    loc = other.loc()
+                                error!(
+                                    other.loc(),
+                                    "expected number, register, memory or char, found `{other}`"
                                 );
                                 Err(())
                             }
                         }
                     }?;
 
-                    statement.op = Op::JGT(token);
+                    statement.op = Op::PRT(token);
                     statement.body.push(op1);
 
+                    if let Some(format) = Statement::parse_format(tokens, &statement.op)? {
+                        statement.body.push(format);
+                    }
+
                     return Ok(Some(statement));
                 }
-                "jeq" => {
+                "dbg" => {
                     if tokens.len() < 1 {
-                        eprintln!("{loc}: expected one operand for `jeq`", loc = loc);
+                        error!(loc, "expected one operand for `dbg`");
                         return Err(());
                     }
 
                     let op1 = {
                         let op = tokens.remove(0);
                         match op {
-                            Token::Identifier { .. } => Ok(op),
+                            Token::Number { .. } => Ok(op),
+                            Token::Register { .. } => Ok(op),
+                            Token::Memory { .. } => Ok(op),
+                            Token::Char { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected label identifier, found `{other}`",
-                                    loc = other.loc()
+                                error!(
+                                    other.loc(),
+                                    "expected number, register, memory or char, found `{other}`"
                                 );
                                 Err(())
                             }
                         }
                     }?;
 
-                    statement.op = Op::JEQ(token);
+                    statement.op = Op::DBG(token);
                     statement.body.push(op1);
 
                     return Ok(Some(statement));
                 }
-                "jne" => {
+                "@" => {
                     if tokens.len() < 1 {
-                        eprintln!("{loc}: expected one operand for `jne`", loc = loc);
+                        error!(loc, "expected one operand for `@`");
                         return Err(());
                     }
 
@@ -301,81 +656,173 @@ impl Statement {
                         match op {
                             Token::Identifier { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected label identifier, found `{other}`",
-                                    loc = other.loc()
-                                );
+                                error!(other.loc(), "expected identifier, found `{other}`");
                                 Err(())
                             }
                         }
                     }?;
 
-                    statement.op = Op::JNE(token);
+                    statement.op = Op::INC(token);
                     statement.body.push(op1);
 
                     return Ok(Some(statement));
                 }
-                "jmp" => {
+                "rdn" => {
                     if tokens.len() < 1 {
-                        eprintln!("{loc}: expected one operand for `jmp`", loc = loc);
+                        error!(loc, "expected one operand for `rdn`");
                         return Err(());
                     }
 
                     let op1 = {
                         let op = tokens.remove(0);
                         match op {
-                            Token::Identifier { .. } => Ok(op),
+                            Token::Register { .. } => Ok(op),
+                            Token::Memory { .. } => Ok(op),
+                            other => {
+                                error!(other.loc(), "expected register or memory, found `{other}`");
+                                Err(())
+                            }
+                        }
+                    }?;
+
+                    statement.op = Op::RDN(token);
+                    statement.body.push(op1);
+
+                    return Ok(Some(statement));
+                }
+
+                "rdc" => {
+                    if tokens.len() < 1 {
+                        error!(loc, "expected one operand for `rdc`");
+                        return Err(());
+                    }
+
+                    let op1 = {
+                        let op = tokens.remove(0);
+                        match op {
+                            Token::Register { .. } => Ok(op),
+                            Token::Memory { .. } => Ok(op),
+                            other => {
+                                error!(other.loc(), "expected register or memory, found `{other}`");
+                                Err(())
+                            }
+                        }
+                    }?;
+
+                    statement.op = Op::RDC(token);
+                    statement.body.push(op1);
+
+                    return Ok(Some(statement));
+                }
+
+                "rln" => {
+                    if tokens.len() < 2 {
+                        error!(loc, "expected two operands for `rln`");
+                        return Err(());
+                    }
+
+                    let op1 = {
+                        let op = tokens.remove(0);
+                        match op {
+                            Token::Memory { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected label identifier, found `{other}`",
-                                    loc = other.loc()
+                                error!(other.loc(), "expected memory, found `{other}`");
+                                Err(())
+                            }
+                        }
+                    }?;
+
+                    let op2 = {
+                        let op = tokens.remove(0);
+                        match op {
+                            Token::Number { .. } => Ok(op),
+                            Token::Register { .. } => Ok(op),
+                            Token::Memory { .. } => Ok(op),
+                            other => {
+                                error!(
+                                    other.loc(),
+                                    "expected number, register or memory, found `{other}`"
                                 );
                                 Err(())
                             }
                         }
                     }?;
 
-                    statement.op = Op::JMP(token);
+                    statement.op = Op::RLN(token);
                     statement.body.push(op1);
+                    statement.body.push(op2);
 
                     return Ok(Some(statement));
                 }
-                "run" => {
+
+                "sys" => {
                     if tokens.len() < 1 {
-                        eprintln!("{loc}: expected one operand for `run`", loc = loc);
+                        error!(loc, "expected one operand for `sys`");
                         return Err(());
                     }
 
                     let op1 = {
                         let op = tokens.remove(0);
                         match op {
-                            Token::Identifier { .. } => Ok(op),
+                            Token::Number { .. } => Ok(op),
+                            Token::Register { .. } => Ok(op),
+                            Token::Memory { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected label identifier, found `{other}`",
-                                    loc = other.loc()
+                                error!(
+                                    other.loc(),
+                                    "expected number, register or memory, found `{other}`"
                                 );
                                 Err(())
                             }
                         }
                     }?;
 
-                    statement.op = Op::RUN(token);
+                    statement.op = Op::SYS(token);
                     statement.body.push(op1);
 
                     return Ok(Some(statement));
                 }
-                "ret" => {
-                    statement.op = Op::RET(token);
+
+                "ivt" => {
+                    if tokens.len() < 2 {
+                        error!(loc, "expected two operands for `ivt`");
+                        return Err(());
+                    }
+
+                    let op1 = {
+                        let op = tokens.remove(0);
+                        match op {
+                            Token::Number { .. } => Ok(op),
+                            other => {
+                                error!(other.loc(), "expected interrupt number, found `{other}`");
+                                Err(())
+                            }
+                        }
+                    }?;
+
+                    let op2 = Statement::label_operand(tokens.remove(0))?;
+
+                    statement.op = Op::IVT(token);
+                    statement.body.push(op1);
+                    statement.body.push(op2);
+
                     return Ok(Some(statement));
                 }
-                "die" => {
-                    statement.op = Op::DIE(token);
+                "ei" => {
+                    statement.op = Op::EI(token);
                     return Ok(Some(statement));
                 }
-                "out" => {
+                "di" => {
+                    statement.op = Op::DI(token);
+                    return Ok(Some(statement));
+                }
+                "iret" => {
+                    statement.op = Op::IRET(token);
+                    return Ok(Some(statement));
+                }
+                "tmr" => {
                     if tokens.len() < 1 {
-                        eprintln!("{loc}: expected one operand for `out`", loc = loc);
+                        error!(loc, "expected one operand for `tmr`");
                         return Err(());
                     }
 
@@ -385,202 +832,318 @@ impl Statement {
                             Token::Number { .. } => Ok(op),
                             Token::Register { .. } => Ok(op),
                             Token::Memory { .. } => Ok(op),
-                            Token::Char { .. } => Ok(op),
                             other => {
-                                eprintln!("{loc}: expected number, register, memory or char, found `{other}`", loc = other.loc());
+                                error!(
+                                    other.loc(),
+                                    "expected number, register or memory, found `{other}`"
+                                );
                                 Err(())
                             }
                         }
                     }?;
 
-                    statement.op = Op::OUT(token);
+                    statement.op = Op::TMR(token);
                     statement.body.push(op1);
 
                     return Ok(Some(statement));
                 }
-                "prt" => {
+
+                "rdk" => {
                     if tokens.len() < 1 {
-                        eprintln!("{loc}: expected one operand for `prt`", loc = loc);
+                        error!(loc, "expected one operand for `rdk`");
                         return Err(());
                     }
 
                     let op1 = {
                         let op = tokens.remove(0);
                         match op {
-                            Token::Number { .. } => Ok(op),
                             Token::Register { .. } => Ok(op),
                             Token::Memory { .. } => Ok(op),
-                            Token::Char { .. } => Ok(op),
                             other => {
-                                eprintln!("{loc}: expected number, register, memory or char, found `{other}`", loc = other.loc());
+                                error!(other.loc(), "expected register or memory, found `{other}`");
                                 Err(())
                             }
                         }
                     }?;
 
-                    statement.op = Op::PRT(token);
+                    statement.op = Op::RDK(token);
                     statement.body.push(op1);
 
                     return Ok(Some(statement));
                 }
-                "dbg" => {
+
+                "ots" => {
                     if tokens.len() < 1 {
-                        eprintln!("{loc}: expected one operand for `dbg`", loc = loc);
+                        error!(loc, "expected one operand for `ots`");
                         return Err(());
                     }
 
                     let op1 = {
                         let op = tokens.remove(0);
                         match op {
-                            Token::Number { .. } => Ok(op),
-                            Token::Register { .. } => Ok(op),
                             Token::Memory { .. } => Ok(op),
-                            Token::Char { .. } => Ok(op),
                             other => {
-                                eprintln!("{loc}: expected number, register, memory or char, found `{other}`", loc = other.loc());
+                                error!(other.loc(), "expected memory, found `{other}`");
                                 Err(())
                             }
                         }
                     }?;
 
-                    statement.op = Op::DBG(token);
+                    statement.op = Op::OTS(token);
                     statement.body.push(op1);
 
                     return Ok(Some(statement));
                 }
-                "@" => {
-                    if tokens.len() < 1 {
-                        eprintln!("{loc}: expected one operand for `@`", loc = loc);
+                "len" => {
+                    if tokens.len() < 2 {
+                        error!(loc, "expected two operands for `len`");
                         return Err(());
                     }
 
                     let op1 = {
                         let op = tokens.remove(0);
                         match op {
-                            Token::Identifier { .. } => Ok(op),
+                            Token::Memory { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected identifier, found `{other}`",
-                                    loc = other.loc()
-                                );
+                                error!(other.loc(), "expected memory, found `{other}`");
                                 Err(())
                             }
                         }
                     }?;
 
-                    statement.op = Op::INC(token);
+                    let op2 = {
+                        let op = tokens.remove(0);
+                        match op {
+                            Token::Register { .. } => Ok(op),
+                            Token::Memory { .. } => Ok(op),
+                            other => {
+                                error!(other.loc(), "expected register or memory, found `{other}`");
+                                Err(())
+                            }
+                        }
+                    }?;
+
+                    statement.op = Op::LEN(token);
                     statement.body.push(op1);
+                    statement.body.push(op2);
 
                     return Ok(Some(statement));
                 }
-                "rdn" => {
-                    if tokens.len() < 1 {
-                        eprintln!("{loc}: expected one operand for `rdn`", loc = loc);
+                "cpy" => {
+                    if tokens.len() < 3 {
+                        error!(loc, "expected three operands for `cpy`");
                         return Err(());
                     }
 
                     let op1 = {
                         let op = tokens.remove(0);
                         match op {
+                            Token::Memory { .. } => Ok(op),
+                            other => {
+                                error!(other.loc(), "expected memory, found `{other}`");
+                                Err(())
+                            }
+                        }
+                    }?;
+
+                    let op2 = {
+                        let op = tokens.remove(0);
+                        match op {
+                            Token::Memory { .. } => Ok(op),
+                            other => {
+                                error!(other.loc(), "expected memory, found `{other}`");
+                                Err(())
+                            }
+                        }
+                    }?;
+
+                    let op3 = {
+                        let op = tokens.remove(0);
+                        match op {
+                            Token::Number { .. } => Ok(op),
                             Token::Register { .. } => Ok(op),
                             Token::Memory { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected register or memory, found `{other}`",
-                                    loc = other.loc()
+                                error!(
+                                    other.loc(),
+                                    "expected number, register or memory, found `{other}`"
                                 );
                                 Err(())
                             }
                         }
                     }?;
 
-                    statement.op = Op::RDN(token);
+                    statement.op = Op::CPY(token);
                     statement.body.push(op1);
+                    statement.body.push(op2);
+                    statement.body.push(op3);
 
                     return Ok(Some(statement));
                 }
+                "cmps" => {
+                    if tokens.len() < 2 {
+                        error!(loc, "expected two operands for `cmps`");
+                        return Err(());
+                    }
 
-                "rdc" => {
+                    let op1 = {
+                        let op = tokens.remove(0);
+                        match op {
+                            Token::Memory { .. } => Ok(op),
+                            other => {
+                                error!(other.loc(), "expected memory, found `{other}`");
+                                Err(())
+                            }
+                        }
+                    }?;
+
+                    let op2 = {
+                        let op = tokens.remove(0);
+                        match op {
+                            Token::Memory { .. } => Ok(op),
+                            other => {
+                                error!(other.loc(), "expected memory, found `{other}`");
+                                Err(())
+                            }
+                        }
+                    }?;
+
+                    statement.op = Op::CMPS(token);
+                    statement.body.push(op1);
+                    statement.body.push(op2);
+
+                    return Ok(Some(statement));
+                }
+                "prx" => {
                     if tokens.len() < 1 {
-                        eprintln!("{loc}: expected one operand for `rdc`", loc = loc);
+                        error!(loc, "expected one operand for `prx`");
                         return Err(());
                     }
 
                     let op1 = {
                         let op = tokens.remove(0);
                         match op {
+                            Token::Number { .. } => Ok(op),
                             Token::Register { .. } => Ok(op),
                             Token::Memory { .. } => Ok(op),
+                            Token::Char { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected register or memory, found `{other}`",
-                                    loc = other.loc()
+                                error!(
+                                    other.loc(),
+                                    "expected number, register, memory or char, found `{other}`"
                                 );
                                 Err(())
                             }
                         }
                     }?;
 
-                    statement.op = Op::RDC(token);
+                    statement.op = Op::PRX(token);
                     statement.body.push(op1);
 
+                    if let Some(format) = Statement::parse_format(tokens, &statement.op)? {
+                        statement.body.push(format);
+                    }
+
                     return Ok(Some(statement));
                 }
-
-                "rln" => {
-                    if tokens.len() < 2 {
-                        eprintln!("{loc}: expected two operands for `rln`", loc = loc);
+                "prb" => {
+                    if tokens.len() < 1 {
+                        error!(loc, "expected one operand for `prb`");
                         return Err(());
                     }
 
                     let op1 = {
                         let op = tokens.remove(0);
                         match op {
+                            Token::Number { .. } => Ok(op),
+                            Token::Register { .. } => Ok(op),
                             Token::Memory { .. } => Ok(op),
+                            Token::Char { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected memory, found `{other}`",
-                                    loc = other.loc()
+                                error!(
+                                    other.loc(),
+                                    "expected number, register, memory or char, found `{other}`"
                                 );
                                 Err(())
                             }
                         }
                     }?;
 
-                    let op2 = {
+                    statement.op = Op::PRB(token);
+                    statement.body.push(op1);
+
+                    if let Some(format) = Statement::parse_format(tokens, &statement.op)? {
+                        statement.body.push(format);
+                    }
+
+                    return Ok(Some(statement));
+                }
+                "prs" => {
+                    if tokens.len() < 1 {
+                        error!(loc, "expected one operand for `prs`");
+                        return Err(());
+                    }
+
+                    let op1 = {
                         let op = tokens.remove(0);
                         match op {
                             Token::Number { .. } => Ok(op),
                             Token::Register { .. } => Ok(op),
                             Token::Memory { .. } => Ok(op),
+                            Token::Char { .. } => Ok(op),
                             other => {
-                                eprintln!(
-                                    "{loc}: expected number, register or memory, found `{other}`",
-                                    loc = other.loc()
+                                error!(
+                                    other.loc(),
+                                    "expected number, register, memory or char, found `{other}`"
                                 );
                                 Err(())
                             }
                         }
                     }?;
 
-                    statement.op = Op::RLN(token);
+                    statement.op = Op::PRS(token);
+                    statement.body.push(op1);
+
+                    if let Some(format) = Statement::parse_format(tokens, &statement.op)? {
+                        statement.body.push(format);
+                    }
+
+                    return Ok(Some(statement));
+                }
+                "export" => {
+                    if tokens.len() < 1 {
+                        error!(loc, "expected one operand for `export`");
+                        return Err(());
+                    }
+
+                    let op1 = {
+                        let op = tokens.remove(0);
+                        match op {
+                            Token::Identifier { .. } => Ok(op),
+                            other => {
+                                error!(other.loc(), "expected label name, found `{other}`");
+                                Err(())
+                            }
+                        }
+                    }?;
+
+                    statement.op = Op::EXPORT(token);
                     statement.body.push(op1);
-                    statement.body.push(op2);
 
                     return Ok(Some(statement));
                 }
 
                 _ => {
-                    eprintln!("{loc}: unknown keyword `{value}`");
+                    error!(loc, "unknown keyword `{value}`");
                     return Err(());
                 }
             },
             other => {
-                eprint!("{loc}: expected keyword", loc = other.loc());
-                if statement.label.is_none() {
-                    eprint!("or label")
-                }
-                eprint!(", found `{other}`");
+                let expected = match statement.label {
+                    Some(_) => "keyword",
+                    None => "keyword or label",
+                };
+                error!(other.loc(), "expected {expected}, found `{other}`");
                 return Err(());
             }
         }
@@ -0,0 +1,22 @@
+/// Standard library bundled into the binary, included with `@ std/<name>`.
+const FILES: [(&str, &str); 2] = [
+    ("math", include_str!("stdlib/math.dis")),
+    ("print", include_str!("stdlib/print.dis")),
+];
+
+/// path diagnostics show for bundled files
+pub fn path(name: &str) -> String {
+    format!("<std>/{name}.dis")
+}
+
+/// source of `std/<name>`
+pub fn source(name: &str) -> Option<&'static str> {
+    FILES
+        .iter()
+        .find(|(file, _)| *file == name)
+        .map(|(_, source)| *source)
+}
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    FILES.iter().map(|(name, _)| *name)
+}
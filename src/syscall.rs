@@ -0,0 +1,56 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Word;
+use crate::{Result, DIS};
+
+/// Host function called by `sys <id>`, it reads its arguments from and
+/// writes its results to the machine. An `Err` reports the syscall as failed
+/// and ends the program with exit code 1.
+pub type Syscall = Box<dyn FnMut(&mut DIS) -> Result<()>>;
+
+/// ends the program with exit code `#0`
+pub const EXIT: Word = 0;
+/// current Unix time in seconds, low word in `#0` and high word in `#1`
+pub const TIME: Word = 1;
+/// pseudo random word in `#0`
+pub const RANDOM: Word = 2;
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn defaults() -> Vec<(Word, Syscall)> {
+    let exit: Syscall = Box::new(|dis| {
+        dis.exit_code = dis.registers["0"];
+        dis.die = true;
+        Ok(())
+    });
+
+    let time: Syscall = Box::new(|dis| {
+        let now = unix_time();
+        let width = dis.config.word_width;
+        dis.registers
+            .insert("0".to_string(), now as Word & width.mask());
+        dis.registers.insert(
+            "1".to_string(),
+            (now >> width.bits()) as Word & width.mask(),
+        );
+        Ok(())
+    });
+
+    // xorshift32, must not be seeded with zero
+    let mut state = (unix_time() as u32) | 1;
+    let random: Syscall = Box::new(move |dis| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        dis.registers
+            .insert("0".to_string(), state & dis.config.word_width.mask());
+        Ok(())
+    });
+
+    vec![(EXIT, exit), (TIME, time), (RANDOM, random)]
+}
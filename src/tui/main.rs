@@ -0,0 +1,597 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::Frame;
+
+use dis::diagnostic;
+use dis::input::Typed;
+use dis::lexer::Location;
+use dis::statement::{Op, Statement};
+use dis::{stdlib, DIS};
+
+fn usage(program: &str) {
+    println!("Usage: {} [options] <program.dis>", program);
+    println!("Options:");
+    println!("  -I <dir>          search <dir> for `@` includes");
+    println!("  --speed <steps>   steps per frame when continuing (default 1000)");
+}
+
+/// time between frames while the program runs
+const FRAME: Duration = Duration::from_millis(16);
+
+/// words scrolled by the memory keys, like `vis`
+const MEM_STEP: usize = 32;
+const MEM_ROW: usize = 8;
+
+#[derive(Clone, Copy)]
+enum MemMode {
+    Hex,
+    Char,
+}
+
+impl fmt::Display for MemMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemMode::Hex => write!(f, "HEX"),
+            MemMode::Char => write!(f, "CHR"),
+        }
+    }
+}
+
+enum Mode {
+    N,
+    /// typing a line for the program to read
+    I,
+    /// continuing until a breakpoint, the end or a read without input
+    R,
+}
+
+/// where a statement is in its file
+fn location(statement: &Statement) -> Option<&Location> {
+    match (statement.op.token(), &statement.label) {
+        (Some(token), _) => Some(token.loc()),
+        (None, Some(label)) => Some(label.loc()),
+        (None, None) => None,
+    }
+}
+
+fn hex_digits(dis: &DIS) -> usize {
+    dis.config.word_width.bits() as usize / 4
+}
+
+struct Debugger {
+    dis: DIS,
+    speed: usize,
+    mode: Mode,
+    mem_mode: MemMode,
+    mem_start: usize,
+
+    /// source lines of every file of the program
+    sources: HashMap<String, Vec<String>>,
+    /// file and line the listing is at, follows the program when it stops
+    cursor: (String, usize),
+    breakpoints: HashSet<(String, usize)>,
+
+    out: Vec<u8>,
+    inp: Typed,
+    line: String,
+    /// why the program stopped, or what went wrong
+    status: String,
+}
+
+impl Debugger {
+    fn new(dis: DIS, speed: usize) -> Self {
+        let sources = dis
+            .sources()
+            .iter()
+            .map(|file| {
+                let source = match file.strip_prefix("<std>/") {
+                    Some(name) => name
+                        .strip_suffix(".dis")
+                        .and_then(stdlib::source)
+                        .map(str::to_string),
+                    None => fs::read_to_string(file).ok(),
+                };
+                let lines = source
+                    .unwrap_or_default()
+                    .lines()
+                    .map(String::from)
+                    .collect();
+                (file.clone(), lines)
+            })
+            .collect();
+
+        let mut debugger = Debugger {
+            dis,
+            speed,
+            mode: Mode::N,
+            mem_mode: MemMode::Hex,
+            mem_start: 0,
+            sources,
+            cursor: (String::new(), 1),
+            breakpoints: HashSet::new(),
+            out: Vec::new(),
+            inp: Typed::new(),
+            line: String::new(),
+            status: String::new(),
+        };
+        debugger.follow();
+        debugger
+    }
+
+    /// the statement that runs next
+    fn current(&self) -> Option<&Location> {
+        self.dis.program.get(self.dis.pc).and_then(location)
+    }
+
+    /// moves the listing to the statement that runs next
+    fn follow(&mut self) {
+        if let Some(loc) = self.current() {
+            self.cursor = (loc.file().to_string(), loc.line());
+        } else if self.cursor.0.is_empty() {
+            let file = self.dis.sources().first().cloned().unwrap_or_default();
+            self.cursor = (file, 1);
+        }
+    }
+
+    /// whether the next statement reads a line that has not been typed yet
+    fn waiting(&self) -> bool {
+        let next_is_read = matches!(
+            self.dis
+                .program
+                .get(self.dis.pc)
+                .map(|statement| &statement.op),
+            Some(Op::RDC(_) | Op::RDN(_) | Op::RLN(_))
+        );
+        next_is_read && !self.inp.has_line() && !self.inp.closed
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        self.current().is_some_and(|loc| {
+            self.breakpoints
+                .contains(&(loc.file().to_string(), loc.line()))
+        })
+    }
+
+    /// runs one statement, true while the program can go on
+    fn step(&mut self) -> bool {
+        let dis = &mut self.dis;
+        let out = &mut self.out;
+        let inp = &mut self.inp;
+
+        // a panic is a bug in the machine, it ends the program and not the
+        // debugger
+        let (result, diagnostics) =
+            diagnostic::capture(|| panic::catch_unwind(AssertUnwindSafe(|| dis.step(out, inp))));
+
+        let mut messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+        if result.is_err() {
+            messages.push(format!("runtime error at statement {}", self.dis.pc));
+            self.dis.exit_code = 1;
+            self.dis.die = true;
+        }
+        if self.dis.die {
+            messages.push(format!("exited with code {}", self.dis.exit_code));
+        }
+        if !messages.is_empty() {
+            self.status = messages.join(", ");
+        }
+
+        !self.dis.die
+    }
+
+    /// steps once, asking for a line first when the step reads one
+    fn step_once(&mut self) {
+        if self.dis.die {
+            return;
+        }
+
+        if self.waiting() {
+            self.mode = Mode::I;
+            return;
+        }
+
+        self.status.clear();
+        self.step();
+        self.follow();
+    }
+
+    /// one frame of continuing
+    fn run_frame(&mut self) {
+        for _ in 0..self.speed {
+            if self.at_breakpoint() {
+                self.status = "stopped at a breakpoint".to_string();
+                self.mode = Mode::N;
+                break;
+            }
+
+            if self.waiting() {
+                self.status = "waiting for input".to_string();
+                self.mode = Mode::I;
+                break;
+            }
+
+            if !self.step() {
+                self.mode = Mode::N;
+                break;
+            }
+        }
+
+        self.follow();
+    }
+
+    fn restart(&mut self) {
+        self.dis.restart_program();
+        self.out.clear();
+        self.inp.bytes.clear();
+        self.inp.closed = false;
+        self.status.clear();
+        self.mode = Mode::N;
+        self.follow();
+    }
+
+    fn toggle_breakpoint(&mut self) {
+        if !self.breakpoints.remove(&self.cursor) {
+            self.breakpoints.insert(self.cursor.clone());
+        }
+    }
+
+    fn move_cursor(&mut self, down: bool) {
+        let lines = self.sources.get(&self.cursor.0).map_or(1, Vec::len).max(1);
+        self.cursor.1 = match down {
+            true => (self.cursor.1 + 1).min(lines),
+            false => (self.cursor.1 - 1).max(1),
+        };
+    }
+
+    fn scroll_memory(&mut self, forward: bool) {
+        let len = self.dis.memory.len();
+        self.mem_start = match forward {
+            true => (self.mem_start + MEM_STEP).min(len.saturating_sub(MEM_STEP)),
+            false => self.mem_start.saturating_sub(MEM_STEP),
+        };
+    }
+
+    /// handles a key, false to quit
+    fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        if code == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+
+        match self.mode {
+            Mode::N => match code {
+                KeyCode::Char(' ') => self.step_once(),
+                KeyCode::Char('g') if !self.dis.die => {
+                    self.status.clear();
+                    self.mode = Mode::R;
+                    // leave the breakpoint the program is stopped at
+                    if !self.waiting() && self.step() {
+                        self.run_frame();
+                    } else {
+                        self.mode = Mode::N;
+                        self.follow();
+                    }
+                }
+                KeyCode::Char('b') => self.toggle_breakpoint(),
+                KeyCode::Char('r') => self.restart(),
+                KeyCode::Char('c') => self.mem_mode = MemMode::Char,
+                KeyCode::Char('h') => self.mem_mode = MemMode::Hex,
+                KeyCode::Char('i') => self.mode = Mode::I,
+                KeyCode::Up | KeyCode::Char('k') => self.move_cursor(false),
+                KeyCode::Down | KeyCode::Char('j') => self.move_cursor(true),
+                KeyCode::Left => self.scroll_memory(false),
+                KeyCode::Right => self.scroll_memory(true),
+                KeyCode::Char('q') | KeyCode::Esc => return false,
+                _ => {}
+            },
+            Mode::I => match code {
+                KeyCode::Enter => {
+                    self.inp.push_line(&self.line);
+                    self.line.clear();
+                    self.status.clear();
+                    self.mode = Mode::N;
+                }
+                KeyCode::Char('d') if modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.inp.bytes.extend(self.line.bytes());
+                    self.line.clear();
+                    self.inp.closed = true;
+                    self.mode = Mode::N;
+                }
+                KeyCode::Backspace => {
+                    self.line.pop();
+                }
+                KeyCode::Char(c) => self.line.push(c),
+                KeyCode::Esc => self.mode = Mode::N,
+                _ => {}
+            },
+            Mode::R => match code {
+                KeyCode::Char('g') | KeyCode::Esc => {
+                    self.status = "paused".to_string();
+                    self.mode = Mode::N;
+                }
+                KeyCode::Char('q') => return false,
+                _ => {}
+            },
+        }
+
+        true
+    }
+}
+
+fn draw_source(f: &mut Frame, area: Rect, debugger: &Debugger) {
+    let (file, cursor) = &debugger.cursor;
+    let lines: &[String] = debugger.sources.get(file).map_or(&[], Vec::as_slice);
+    let current = debugger
+        .current()
+        .filter(|loc| loc.file() == file)
+        .map(|loc| loc.line());
+
+    // keep the cursor in the middle
+    let height = area.height.saturating_sub(2) as usize;
+    let first = cursor.saturating_sub(height / 2).max(1);
+
+    let listing: Vec<Line> = lines
+        .iter()
+        .enumerate()
+        .skip(first - 1)
+        .take(height)
+        .map(|(idx, text)| {
+            let line = idx + 1;
+            let breakpoint = debugger.breakpoints.contains(&(file.clone(), line));
+
+            let marker = match (breakpoint, line == *cursor) {
+                (true, _) => Span::styled("●", Style::new().fg(Color::Red)),
+                (false, true) => Span::raw(">"),
+                (false, false) => Span::raw(" "),
+            };
+
+            let style = match Some(line) == current {
+                true => Style::new().bg(Color::Blue).add_modifier(Modifier::BOLD),
+                false => Style::new(),
+            };
+
+            Line::from(vec![
+                marker,
+                Span::styled(format!("{line:>4} "), Style::new().fg(Color::DarkGray)),
+                Span::styled(text.clone(), style),
+            ])
+        })
+        .collect();
+
+    let block = Block::bordered().title(format!(" {file} "));
+    f.render_widget(Paragraph::new(listing).block(block), area);
+}
+
+fn draw_registers(f: &mut Frame, area: Rect, dis: &DIS) {
+    let names = dis.config.register_names();
+    let lines: Vec<Line> = names
+        .chunks(4)
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .map(|name| {
+                    let value = dis.registers[name];
+                    format!("{:>2}: {:0width$x}", name, value, width = hex_digits(dis))
+                })
+                .collect();
+            Line::raw(cells.join("  "))
+        })
+        .collect();
+
+    f.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" REG ")),
+        area,
+    );
+}
+
+fn draw_flags(f: &mut Frame, area: Rect, dis: &DIS) {
+    let interrupts = &dis.interrupts;
+    let pending: Vec<String> = interrupts.pending.iter().map(|i| i.to_string()).collect();
+
+    let mut s = format!(
+        "CMP {}  INT {} PEND [{}] DEPTH {}  RET {}",
+        dis.flags(),
+        match interrupts.enabled {
+            true => "ON",
+            false => "OFF",
+        },
+        pending.join(" "),
+        interrupts.frames.len(),
+        dis.return_stack().len()
+    );
+    if interrupts.timer != 0 {
+        s.push_str(&format!("  TMR {}", interrupts.timer));
+    }
+
+    f.render_widget(Paragraph::new(s).block(Block::bordered()), area);
+}
+
+fn draw_memory(f: &mut Frame, area: Rect, debugger: &Debugger) {
+    let dis = &debugger.dis;
+    let rows = area.height.saturating_sub(2) as usize;
+    let start = debugger.mem_start;
+    let end = (start + rows * MEM_ROW).min(dis.memory.len());
+
+    let lines: Vec<Line> = (start..end)
+        .step_by(MEM_ROW)
+        .map(|row| {
+            let cells: Vec<String> = dis.memory[row..(row + MEM_ROW).min(end)]
+                .iter()
+                .map(|val| match debugger.mem_mode {
+                    MemMode::Hex => format!("{:0width$x}", val, width = hex_digits(dis)),
+                    MemMode::Char => match char::from_u32(*val).filter(|c| !c.is_control()) {
+                        Some(c) => format!("{:>width$}", c, width = hex_digits(dis)),
+                        None => "#".repeat(hex_digits(dis)),
+                    },
+                })
+                .collect();
+            Line::raw(format!("{row:>5}: {}", cells.join(" ")))
+        })
+        .collect();
+
+    let title = format!(" MEM <{start}-{end}> : {} ", debugger.mem_mode);
+    f.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(title)),
+        area,
+    );
+}
+
+fn draw_output(f: &mut Frame, area: Rect, debugger: &Debugger) {
+    let text = String::from_utf8_lossy(&debugger.out);
+    let lines: Vec<&str> = text.split('\n').collect();
+    let height = area.height.saturating_sub(2) as usize;
+    let shown: Vec<Line> = lines[lines.len().saturating_sub(height)..]
+        .iter()
+        .map(|line| Line::raw(*line))
+        .collect();
+
+    f.render_widget(
+        Paragraph::new(shown).block(Block::bordered().title(" OUT ")),
+        area,
+    );
+}
+
+fn draw_input(f: &mut Frame, area: Rect, debugger: &Debugger) {
+    let typed: Vec<u8> = debugger.inp.bytes.iter().copied().collect();
+    let typed = String::from_utf8_lossy(&typed).replace('\n', "⏎");
+
+    let prompt = match debugger.mode {
+        Mode::N => format!(">  {typed}"),
+        Mode::I => format!(">>>{typed}{}_", debugger.line),
+        Mode::R => "RUN (g to pause)".to_string(),
+    };
+
+    let help = match debugger.mode {
+        Mode::N => "space step  g continue  b breakpoint  j/k move  i input  r restart  c/h memory  ←/→ scroll  q quit",
+        Mode::I => "enter send line  ctrl-d end input  esc cancel",
+        Mode::R => "g/esc pause  q quit",
+    };
+
+    let status = match debugger.status.is_empty() {
+        true => Line::styled(help, Style::new().fg(Color::DarkGray)),
+        false => Line::styled(debugger.status.clone(), Style::new().fg(Color::Red)),
+    };
+
+    f.render_widget(Paragraph::new(vec![Line::raw(prompt), status]), area);
+}
+
+fn draw(f: &mut Frame, debugger: &Debugger) {
+    let dis = &debugger.dis;
+    let register_rows = dis.config.register_names().len().div_ceil(4) as u16;
+
+    let [main, output, input] = Layout::vertical([
+        Constraint::Min(10),
+        Constraint::Length(8),
+        Constraint::Length(2),
+    ])
+    .areas(f.area());
+    let [source, side] =
+        Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(main);
+    let [registers, flags, memory] = Layout::vertical([
+        Constraint::Length(register_rows + 2),
+        Constraint::Length(3),
+        Constraint::Min(3),
+    ])
+    .areas(side);
+
+    draw_source(f, source, debugger);
+    draw_registers(f, registers, dis);
+    draw_flags(f, flags, dis);
+    draw_memory(f, memory, debugger);
+    draw_output(f, output, debugger);
+    draw_input(f, input, debugger);
+}
+
+fn main() -> ExitCode {
+    let mut args: VecDeque<String> = std::env::args().collect();
+
+    let program = args.pop_front().unwrap();
+
+    let mut include_paths = Vec::new();
+    let mut speed = 1000;
+    let mut filepath = None;
+
+    while let Some(arg) = args.pop_front() {
+        match arg.as_str() {
+            "-I" => match args.pop_front() {
+                Some(dir) => include_paths.push(dir.into()),
+                None => {
+                    eprintln!("expected directory after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--speed" => match args.pop_front().map(|steps| steps.parse()) {
+                Some(Ok(steps)) => speed = steps,
+                _ => {
+                    eprintln!("expected number after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ if arg.starts_with("-") => {
+                eprintln!("unknown option `{arg}`");
+                usage(&program);
+                return ExitCode::FAILURE;
+            }
+            _ => filepath = Some(arg),
+        }
+    }
+
+    let Some(filepath) = filepath else {
+        usage(&program);
+        return ExitCode::FAILURE;
+    };
+
+    let mut dis = DIS::new();
+    dis.include_paths = include_paths;
+
+    if dis.load(filepath).is_err() {
+        eprintln!("Error loading program");
+        return ExitCode::FAILURE;
+    }
+
+    let mut debugger = Debugger::new(dis, speed);
+    let mut terminal = ratatui::init();
+
+    loop {
+        if terminal.draw(|f| draw(f, &debugger)).is_err() {
+            break;
+        }
+
+        // only wait for keys while the program is not running
+        let timeout = match debugger.mode {
+            Mode::R => FRAME,
+            _ => Duration::from_secs(60),
+        };
+
+        match event::poll(timeout) {
+            Ok(true) => match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                    if !debugger.key(key.code, key.modifiers) {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            },
+            Ok(false) => {}
+            Err(_) => break,
+        }
+
+        if let Mode::R = debugger.mode {
+            debugger.run_frame();
+        }
+    }
+
+    ratatui::restore();
+
+    ExitCode::SUCCESS
+}
@@ -1,16 +1,30 @@
 use std::collections::VecDeque;
 use std::fmt;
-use std::io::BufReader;
 use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
 
 use raylib::prelude::*;
 
 use itertools::Itertools;
 
+use dis::config::{parse_address, Word};
+use dis::device::Keyboard;
+use dis::framebuffer::{Framebuffer, Palette};
+use dis::input::Typed;
 use dis::lexer::Token;
 use dis::statement::{Op, Statement};
 use dis::DIS;
 
+fn usage(program: &str) {
+    println!("Usage: {} [options] <program.dis>", program);
+    println!("Options:");
+    println!("  --fb <addr>              show memory from <addr> as pixels (default 1024)");
+    println!("  --fb-size <w>x<h>        framebuffer size in pixels (default 64x32)");
+    println!("  --fb-palette <palette>   c64, mono, gray or hex colors like 000000,ff0000");
+    println!("  --keyboard <addr>        map a keyboard fed with key presses in run mode");
+    println!("  --speed <steps>          steps per frame in run mode (default 1000)");
+}
+
 fn format_statement(statement: &Statement) -> String {
     let mut s = String::new();
 
@@ -24,30 +38,7 @@ fn format_statement(statement: &Statement) -> String {
         s.push_str(": ");
     }
 
-    let op = match statement.op {
-        Op::MOV(_) => "mov",
-        Op::ADD(_) => "add",
-        Op::SUB(_) => "sub",
-        Op::CMP(_) => "cmp",
-        Op::JLT(_) => "jlt",
-        Op::JGT(_) => "jgt",
-        Op::JEQ(_) => "jeq",
-        Op::JNE(_) => "jne",
-        Op::JMP(_) => "jmp",
-        Op::RUN(_) => "run",
-        Op::RET(_) => "ret",
-        Op::DIE(_) => "die",
-        Op::OUT(_) => "out",
-        Op::PRT(_) => "prt",
-        Op::DBG(_) => "dbg",
-        Op::INC(_) => "@",
-        Op::RDN(_) => "rdn",
-        Op::RDC(_) => "rdc",
-        Op::RLN(_) => "rln",
-        Op::NOP => "nop",
-    };
-
-    s.push_str(op);
+    s.push_str(statement.op.mnemonic());
 
     for arg in &statement.body {
         s.push_str(format!(" {}", arg).as_str());
@@ -56,6 +47,10 @@ fn format_statement(statement: &Statement) -> String {
     s
 }
 
+fn hex_digits(dis: &DIS) -> usize {
+    dis.config.word_width.bits() as usize / 4
+}
+
 fn draw_registers(d: &mut RaylibDrawHandle, dis: &DIS) {
     let font_size = 24;
     let y_offset = 32 + font_size * 2;
@@ -66,10 +61,39 @@ fn draw_registers(d: &mut RaylibDrawHandle, dis: &DIS) {
         let x = font_size * (idx as i32 % 4) * 4;
         let y = font_size * (idx as i32 / 4) * 2 + 32;
 
-        let s = format!("{:>2}: {:04x}", reg_id, dis.registers[reg_id]);
+        let s = format!(
+            "{:>2}: {:0width$x}",
+            reg_id,
+            dis.registers[reg_id],
+            width = hex_digits(dis)
+        );
         d.draw_text(&s, x, y + y_offset, font_size, Color::WHITE);
     }
 }
+
+fn draw_interrupts(d: &mut RaylibDrawHandle, dis: &DIS) {
+    let font_size = 24;
+    let rows = (dis.registers.len() as i32 + 3) / 4;
+    let y = 32 + font_size * 2 + font_size * rows * 2 + 32;
+
+    let interrupts = &dis.interrupts;
+    let s = format!(
+        "INT {} PEND [{}] DEPTH {}",
+        match interrupts.enabled {
+            true => "ON",
+            false => "OFF",
+        },
+        interrupts.pending.iter().join(" "),
+        interrupts.frames.len()
+    );
+    d.draw_text(&s, 0, y, font_size, Color::WHITE);
+
+    if interrupts.timer != 0 {
+        let s = format!("TMR {}", interrupts.timer);
+        d.draw_text(&s, 0, y + font_size, font_size, Color::WHITE);
+    }
+}
+
 #[derive(Clone, Copy)]
 enum MemMode {
     HEX,
@@ -104,25 +128,66 @@ fn draw_memory(d: &mut RaylibDrawHandle, dis: &DIS, start: usize, step: usize, m
         let y = font_size * (idx as i32 / 4) * 2 + 32;
 
         let s = match mode {
-            MemMode::HEX => format!("{:04x}", val),
-            MemMode::CHAR => {
-                if *val >= 0x20 && *val <= 0x7e {
-                    format!("{}", char::from_u32(*val as u32).unwrap_or('.'))
-                } else {
-                    format!("####")
-                }
-            }
+            MemMode::HEX => format!("{:0width$x}", val, width = hex_digits(dis)),
+            MemMode::CHAR => match char::from_u32(*val).filter(|c| !c.is_control()) {
+                Some(c) => format!("{}", c),
+                None => format!("####"),
+            },
         };
         d.draw_text(&s, x, y + y_offset, font_size, Color::WHITE);
     }
 }
 
+fn draw_framebuffer(d: &mut RaylibDrawHandle, dis: &DIS, fb: &Framebuffer) {
+    let font_size = 24;
+    let y_offset = 32 + font_size * 2;
+    let x_offset = 32 * 13;
+
+    let pane = WINDOW_WIDTH - x_offset - 8;
+    let scale = (pane / fb.width as i32).min(pane / fb.height as i32).max(1);
+
+    d.draw_text(
+        format!(
+            "FB <{}-{}> : {}x{}",
+            fb.base,
+            fb.base + fb.width * fb.height,
+            fb.width,
+            fb.height
+        )
+        .as_str(),
+        x_offset,
+        y_offset - font_size,
+        font_size,
+        Color::WHITE,
+    );
+    for (idx, [r, g, b]) in fb.pixels(dis).into_iter().enumerate() {
+        let x = (idx % fb.width) as i32 * scale + x_offset;
+        let y = (idx / fb.width) as i32 * scale + y_offset + 8;
+
+        d.draw_rectangle(x, y, scale, scale, Color::new(r, g, b, 255));
+    }
+}
+
+/// key code a program reads from the keyboard device
+fn key_code(key: KeyboardKey, shift: bool) -> Option<Word> {
+    let code = key as u32;
+    match key {
+        KeyboardKey::KEY_ENTER => Some(10),
+        KeyboardKey::KEY_BACKSPACE => Some(8),
+        _ if (65..=90).contains(&code) && !shift => Some(code + 32),
+        _ if code < 128 => Some(code),
+        _ => None,
+    }
+}
+
 const WINDOW_WIDTH: i32 = 800;
 const WINDOW_HEIGHT: i32 = 600;
 
 enum Mode {
     N,
     I,
+    /// runs the program, key presses go to the keyboard device
+    K,
 }
 
 fn main() -> ExitCode {
@@ -130,14 +195,82 @@ fn main() -> ExitCode {
 
     let program = args.pop_front().unwrap();
 
-    if args.len() < 1 {
-        println!("Usage: {} <program.dis>", program);
-        return ExitCode::FAILURE;
+    let mut fb = None;
+    let mut keyboard_addr = None;
+    let mut speed = 1000;
+    let mut filepath = None;
+
+    while let Some(arg) = args.pop_front() {
+        match arg.as_str() {
+            "--fb" => match args.pop_front().and_then(|addr| parse_address(&addr)) {
+                Some(addr) => fb = Some(fb.unwrap_or_else(Framebuffer::new).base(addr)),
+                None => {
+                    eprintln!("expected address after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--fb-size" => match args.pop_front().and_then(|s| Framebuffer::parse_size(&s)) {
+                Some((width, height)) => {
+                    fb = Some(fb.unwrap_or_else(Framebuffer::new).size(width, height))
+                }
+                None => {
+                    eprintln!("expected <width>x<height> after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--fb-palette" => match args.pop_front().and_then(|s| Palette::parse(&s)) {
+                Some(palette) => fb = Some(fb.unwrap_or_else(Framebuffer::new).palette(palette)),
+                None => {
+                    eprintln!("expected palette after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--keyboard" => match args.pop_front().and_then(|addr| parse_address(&addr)) {
+                Some(addr) => keyboard_addr = Some(addr),
+                None => {
+                    eprintln!("expected address after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--speed" => match args.pop_front().map(|steps| steps.parse()) {
+                Some(Ok(steps)) => speed = steps,
+                _ => {
+                    eprintln!("expected number after `{arg}`");
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ if arg.starts_with("--") => {
+                eprintln!("unknown option `{arg}`");
+                usage(&program);
+                return ExitCode::FAILURE;
+            }
+            _ => filepath = Some(arg),
+        }
     }
 
-    let filepath = args.pop_front().unwrap();
+    let Some(filepath) = filepath else {
+        usage(&program);
+        return ExitCode::FAILURE;
+    };
 
     let mut dis = DIS::new();
+
+    let mut keys: Option<Arc<Mutex<VecDeque<Word>>>> = None;
+    if let Some(addr) = keyboard_addr {
+        let keyboard = Keyboard::new();
+        keys = Some(keyboard.keys());
+
+        if dis.attach_device(addr, Box::new(keyboard)).is_err() {
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(fb) = &fb {
+        if fb.validate(&dis).is_err() {
+            return ExitCode::FAILURE;
+        }
+    }
+
     if dis.load(filepath).is_err() {
         eprintln!("Error loading program");
         return ExitCode::FAILURE;
@@ -155,10 +288,12 @@ fn main() -> ExitCode {
 
     let mut mem_mode = MemMode::HEX;
     let mut mode = Mode::N;
+    let mut show_fb = fb.is_some();
 
     let mut buf: Vec<u8> = Vec::new();
 
-    let mut locked = false;
+    // lines entered in input mode wait here until the program reads them
+    let mut typed = Typed::new();
     let mut inp = String::new();
 
     while !rl.window_should_close() {
@@ -170,19 +305,11 @@ fn main() -> ExitCode {
             None => false,
         };
 
-        if rl.is_key_pressed(KeyboardKey::KEY_SPACE) {
-            if next_is_read {
-                if locked {
-                    let mut reader = BufReader::new(inp.as_bytes());
-                    dis.step(&mut buf, &mut reader);
-                    inp.clear();
-                    locked = false;
-                } else {
-                    mode = Mode::I;
-                }
+        if rl.is_key_pressed(KeyboardKey::KEY_SPACE) && !matches!(mode, Mode::K) {
+            if next_is_read && !typed.has_line() {
+                mode = Mode::I;
             } else {
-                let mut reader = BufReader::new(inp.as_bytes());
-                dis.step(&mut buf, &mut reader);
+                dis.step(&mut buf, &mut typed);
             }
         }
 
@@ -203,7 +330,6 @@ fn main() -> ExitCode {
             Mode::N => {
                 if rl.is_key_pressed(KeyboardKey::KEY_I) {
                     mode = Mode::I;
-                    locked = false;
                 }
                 if rl.is_key_pressed(KeyboardKey::KEY_C) {
                     mem_mode = MemMode::CHAR;
@@ -216,12 +342,23 @@ fn main() -> ExitCode {
                 if rl.is_key_pressed(KeyboardKey::KEY_R) {
                     dis.restart_program();
                 }
+
+                if rl.is_key_pressed(KeyboardKey::KEY_F) && fb.is_some() {
+                    show_fb = !show_fb;
+                }
+
+                if rl.is_key_pressed(KeyboardKey::KEY_TAB) {
+                    mode = Mode::K;
+                    // drop keys pressed before run mode started
+                    while rl.get_key_pressed().is_some() {}
+                }
             }
             Mode::I => {
                 if rl.is_key_pressed(KeyboardKey::KEY_ENTER) {
                     mode = Mode::N;
-                    locked = true;
-                } else if !locked {
+                    typed.push_line(&inp);
+                    inp.clear();
+                } else {
                     if rl.is_key_pressed(KeyboardKey::KEY_BACKSPACE) {
                         inp.pop();
                     } else if let Some(c) = rl.get_key_pressed() {
@@ -229,6 +366,36 @@ fn main() -> ExitCode {
                     }
                 }
             }
+            Mode::K => {
+                let shift = rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT)
+                    || rl.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT);
+
+                while let Some(key) = rl.get_key_pressed() {
+                    if key == KeyboardKey::KEY_TAB {
+                        mode = Mode::N;
+                        break;
+                    }
+
+                    if let (Some(keys), Some(code)) = (&keys, key_code(key, shift)) {
+                        keys.lock().unwrap().push_back(code);
+                    }
+                }
+
+                // reads wait for input typed in input mode
+                let mut steps = 0;
+                while matches!(mode, Mode::K) && !dis.die && steps < speed {
+                    let next_is_read = matches!(
+                        dis.program.get(dis.pc).map(|statement| &statement.op),
+                        Some(Op::RDC(_) | Op::RDN(_) | Op::RLN(_))
+                    );
+                    if next_is_read && !typed.has_line() {
+                        break;
+                    }
+
+                    dis.step(&mut buf, &mut typed);
+                    steps += 1;
+                }
+            }
         }
 
         let mut d = rl.begin_drawing(&thread);
@@ -244,12 +411,16 @@ fn main() -> ExitCode {
         d.draw_text(&format_statement(cur_statement), 0, 0, 32, Color::WHITE);
 
         draw_registers(&mut d, &dis);
-        draw_memory(&mut d, &dis, mem_start, step, mem_mode);
+        draw_interrupts(&mut d, &dis);
+        match (&fb, show_fb) {
+            (Some(fb), true) => draw_framebuffer(&mut d, &dis, fb),
+            _ => draw_memory(&mut d, &dis, mem_start, step, mem_mode),
+        }
 
         {
             let font_size = 32;
             d.draw_text(
-                buf.iter().map(|v| *v as char).join("").as_str(),
+                String::from_utf8_lossy(&buf).as_ref(),
                 0,
                 WINDOW_HEIGHT - font_size,
                 font_size,
@@ -260,14 +431,16 @@ fn main() -> ExitCode {
         {
             let font_size = 32;
 
+            let pending = String::from_utf8_lossy(typed.bytes.make_contiguous()).replace('\n', " ");
             let s = match mode {
-                Mode::N => format!(">  {inp}"),
+                Mode::N => format!(">  {pending}"),
                 Mode::I => format!(">>>{inp}"),
+                Mode::K => "RUN (tab to stop)".to_string(),
             };
 
-            let color = match locked {
-                true => Color::RED,
-                false => Color::WHITE,
+            let color = match typed.bytes.is_empty() {
+                true => Color::WHITE,
+                false => Color::RED,
             };
 
             d.draw_text(&s, 0, WINDOW_HEIGHT - font_size * 2, font_size, color);